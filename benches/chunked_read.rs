@@ -0,0 +1,38 @@
+//! Reads a 64 KB body chunk by chunk, as clients do through the HTTP Entity Body, either
+//! cloning the whole body at each read or slicing it.
+
+use bytes::Bytes;
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use hps_ble::utils;
+
+const BODY_SIZE: usize = 64 * 1024;
+/// Chunk size of a 247 bytes ATT MTU.
+const CHUNK_SIZE: usize = 244;
+
+fn chunked_read(c: &mut Criterion) {
+    let body: Vec<u8> = (0..BODY_SIZE).map(|i| i as u8).collect();
+    let chunks = utils::get_chunk_count(BODY_SIZE, CHUNK_SIZE) as usize;
+
+    let vec = body.clone();
+    c.bench_function("vec_clone_per_chunk", |b| {
+        b.iter(|| {
+            for index in 0..chunks {
+                let value = vec.clone();
+                black_box(utils::get_chunk(&value, index, CHUNK_SIZE).to_vec());
+            }
+        })
+    });
+
+    let bytes = Bytes::from(body);
+    c.bench_function("bytes_slice_per_chunk", |b| {
+        b.iter(|| {
+            for index in 0..chunks {
+                let value = bytes.clone();
+                black_box(utils::get_chunk_bytes(&value, index, CHUNK_SIZE).to_vec());
+            }
+        })
+    });
+}
+
+criterion_group!(benches, chunked_read);
+criterion_main!(benches);
@@ -0,0 +1,44 @@
+//! Serves the requests to the hosts listed in `HPS_ALLOWED_HOSTS` (comma separated) only.
+//!
+//! ```sh
+//! HPS_ALLOWED_HOSTS=example.com,api.example.com cargo run --example host_allowlist
+//! ```
+
+use clap::Parser;
+use hps_ble::{
+    http::hook::{HookError, ProxyHook, RequestParts},
+    utils, Config, Result, ServerBuilder,
+};
+
+struct HostAllowlist {
+    hosts: Vec<String>,
+}
+
+impl ProxyHook for HostAllowlist {
+    fn on_request(&self, request: &mut RequestParts) -> std::result::Result<(), HookError> {
+        let host = request.url().host_str().unwrap_or_default();
+        if self.hosts.iter().any(|allowed| allowed.eq_ignore_ascii_case(host)) {
+            Ok(())
+        } else {
+            Err(HookError(format!("host '{}' is not allowed", host)))
+        }
+    }
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let hosts = std::env::var("HPS_ALLOWED_HOSTS")
+        .unwrap_or_default()
+        .split(',')
+        .map(|host| host.trim().to_string())
+        .filter(|host| !host.is_empty())
+        .collect();
+
+    let server = ServerBuilder::new(Config::parse())
+        .hook(HostAllowlist { hosts })
+        .start()
+        .await?;
+    utils::handle_signals().await?;
+    server.shutdown().await;
+    Ok(())
+}
@@ -1,28 +1,341 @@
-use std::sync::Arc;
-use tokio::sync::Mutex;
+use crate::http::{audit::AuditRecord, disk_cache::DiskCache, handler::{HttpControlOption, HttpDataStatusBit}, hook::{NoopHook, ProxyHook}, preflight::PreflightEntry, queue::PriorityQueue, spool::{SpoolFile, SpooledBody}};
+use bluer::{gatt::local::CharacteristicNotifier, Adapter, Address};
+use byteorder::{LittleEndian, WriteBytesExt};
+use bytes::Bytes;
+use std::{collections::HashMap, sync::{atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering}, Arc, OnceLock}, time::{Duration, Instant}};
+use tokio::{sync::{broadcast, mpsc, Mutex, RwLock}, task::AbortHandle};
+use tokio_util::sync::CancellationToken;
+use zeroize::Zeroizing;
 
-pub type SharedBuffer = Arc<Mutex<Vec<u8>>>;
+/// Characteristic value, read concurrently by the chunked reads and only locked exclusively
+/// while it's being written.
+pub type SharedBuffer = Arc<RwLock<Vec<u8>>>;
 
-pub struct AppState {
+/// Response value read in chunks, which share its memory instead of copying it.
+pub type SharedBytes = Arc<RwLock<Bytes>>;
+
+/// Notifier of a characteristic, cloned out of its slot so that a notification being delivered
+/// doesn't hold the slot.
+pub type SharedNotifier = Arc<Mutex<CharacteristicNotifier>>;
+
+/// Status written for a device, notified through the HTTP Status Code characteristic.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StatusUpdate {
+    pub device: Address,
+    pub value: Vec<u8>,
+}
+
+/// Notifiers of the characteristics the client subscribed to.
+#[derive(Clone, Default)]
+pub struct Notifiers {
+    pub body: Option<SharedNotifier>,
+    pub headers_changed: Option<SharedNotifier>,
+}
+
+impl Notifiers {
+    /// Drops `notifier`, unless it was replaced in the meantime.
+    fn remove(&mut self, notifier: &SharedNotifier) {
+        for slot in [&mut self.body, &mut self.headers_changed] {
+            if slot.as_ref().is_some_and(|current| Arc::ptr_eq(current, notifier)) {
+                *slot = None;
+            }
+        }
+    }
+}
+
+/// Number of status updates buffered for the notifications, a subscriber lagging further behind
+/// skips the oldest ones.
+pub const STATUS_UPDATES_CAPACITY: usize = 64;
+
+#[derive(Default)]
+pub struct Metrics {
+    pub requests: AtomicU32,
+    pub errors: AtomicU32,
+    pub last_latency_ms: AtomicU32,
+}
+
+impl Metrics {
+    /// Request count, error count and last latency in milliseconds as u32 little endian numbers.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut value = Vec::with_capacity(12);
+        for counter in [&self.requests, &self.errors, &self.last_latency_ms] {
+            value.write_u32::<LittleEndian>(counter.load(Ordering::Relaxed)).unwrap();
+        }
+        value
+    }
+}
+
+/// Outcome counters of the requests, exposed through the Diagnostics characteristic.
+#[derive(Default)]
+pub struct Diagnostics {
+    pub requests: AtomicU32,
+    pub successes: AtomicU32,
+    pub client_errors: AtomicU32,
+    pub server_errors: AtomicU32,
+    pub transport_failures: AtomicU32,
+}
+
+impl Diagnostics {
+    fn counters(&self) -> [&AtomicU32; 5] {
+        [&self.requests, &self.successes, &self.client_errors, &self.server_errors, &self.transport_failures]
+    }
+
+    /// Counts a request that reported `status_code`, which is a transport failure when the
+    /// server couldn't be reached.
+    pub fn record(&self, status_code: u16, transport_failure: bool) {
+        self.requests.fetch_add(1, Ordering::Relaxed);
+        let counter = match status_code {
+            200..=299 => &self.successes,
+            400..=499 => &self.client_errors,
+            500..=599 => &self.server_errors,
+            _ if transport_failure => &self.transport_failures,
+            _ => return,
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// The counters as u32 little endian numbers, in declaration order.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut value = Vec::with_capacity(20);
+        for counter in self.counters() {
+            value.write_u32::<LittleEndian>(counter.load(Ordering::Relaxed)).unwrap();
+        }
+        value
+    }
+
+    pub fn reset(&self) {
+        for counter in self.counters() {
+            counter.store(0, Ordering::Relaxed);
+        }
+    }
+}
+
+/// Summary of a server run, returned once it shuts down.
+#[derive(Clone, Debug)]
+pub struct RunSummary {
+    pub requests: u32,
+    pub errors: u32,
+    pub uptime: Duration,
+}
+
+/// Inputs of a request, as they were when the request was issued.
+#[derive(Clone, Debug)]
+pub struct RequestSnapshot {
+    pub option: HttpControlOption,
+    pub uri: Vec<u8>,
+    pub headers: Vec<u8>,
+    pub body: Vec<u8>,
+    /// Body spooled to disk, in place of `body`.
+    pub spool: Option<SpooledBody>,
+}
+
+/// Request and response state of a connected device, so that devices don't clobber each
+/// other's requests.
+pub struct Session {
+    pub device: Address,
     pub http_uri: SharedBuffer,
-    pub http_headers: SharedBuffer,
+    pub request_headers: SharedBuffer,
+    pub response_headers: SharedBytes,
     pub http_status_code: SharedBuffer,
-    pub http_entity_body: SharedBuffer,
+    pub request_body: SharedBuffer,
+    /// Spool file of the request body once it exceeds `--spool-threshold`, `request_body` is
+    /// empty meanwhile.
+    pub request_spool: Mutex<Option<Arc<SpoolFile>>>,
+    pub response_body: SharedBytes,
     pub https_security: SharedBuffer,
     pub http_headers_body_chunk_idx: SharedBuffer,
     pub http_headers_body_sizes: SharedBuffer,
+    pub headers_changed: SharedBuffer,
+    pub request_timing: SharedBuffer,
+    /// Basic auth credentials (`username:password`) of the next request, zeroized once used.
+    pub basic_auth: Mutex<Zeroizing<Vec<u8>>>,
+    pub request_generation: AtomicU64,
+    pub in_flight: AtomicBool,
+    pub last_request: Mutex<Option<RequestSnapshot>>,
+    pub template_values: Mutex<HashMap<String, String>>,
+    pub headers_fingerprint: Mutex<Option<u32>>,
+    /// Cancelled by the Cancel opcode, or once the request in flight completes.
+    pub active_request: Mutex<Option<CancellationToken>>,
+    pub status_updates: broadcast::Sender<StatusUpdate>,
+    pub notifiers: Mutex<Notifiers>,
+    /// Task pushing the response body through notifications, if any.
+    pub body_stream: Mutex<Option<AbortHandle>>,
+}
+
+impl Session {
+    pub fn new(device: Address, status_updates: broadcast::Sender<StatusUpdate>, notifiers: Notifiers) -> Self {
+        Self {
+            device,
+            http_uri: Arc::new(RwLock::new(Vec::new())),
+            request_headers: Arc::new(RwLock::new(Vec::new())),
+            response_headers: Arc::new(RwLock::new(Bytes::new())),
+            http_status_code: Arc::new(RwLock::new(Vec::new())),
+            request_body: Arc::new(RwLock::new(Vec::new())),
+            request_spool: Mutex::new(None),
+            response_body: Arc::new(RwLock::new(Bytes::new())),
+            https_security: Arc::new(RwLock::new(Vec::new())),
+            http_headers_body_chunk_idx: Arc::new(RwLock::new(vec![0; 8])),
+            http_headers_body_sizes: Arc::new(RwLock::new(Vec::new())),
+            headers_changed: Arc::new(RwLock::new(Vec::new())),
+            request_timing: Arc::new(RwLock::new(Vec::new())),
+            basic_auth: Mutex::new(Zeroizing::new(Vec::new())),
+            request_generation: AtomicU64::new(0),
+            in_flight: AtomicBool::new(false),
+            last_request: Mutex::new(None),
+            template_values: Mutex::new(HashMap::new()),
+            headers_fingerprint: Mutex::new(None),
+            active_request: Mutex::new(None),
+            status_updates,
+            notifiers: Mutex::new(notifiers),
+            body_stream: Mutex::new(None),
+        }
+    }
+
+    /// Flags the current response as read past its last chunk, until the next response.
+    pub async fn mark_chunk_overrun(&self) {
+        if let Some(data_status) = self.http_status_code.write().await.get_mut(2) {
+            *data_status |= HttpDataStatusBit::ChunkOverrun as u8;
+        }
+    }
+}
+
+pub struct AppState {
+    pub sessions: Mutex<HashMap<Address, Arc<Session>>>,
+    /// Status updates of every session, notified through the HTTP Status Code characteristic.
+    pub status_updates: broadcast::Sender<StatusUpdate>,
+    pub adapter_info: SharedBuffer,
+    pub ready: AtomicBool,
+    /// Cancelled once the server is shutting down, new requests are rejected from then on.
+    pub shutdown: CancellationToken,
+    pub metrics: Metrics,
+    pub diagnostics: Diagnostics,
+    pub preflight_cache: Mutex<HashMap<String, PreflightEntry>>,
+    pub host_queues: Mutex<HashMap<String, Arc<PriorityQueue>>>,
+    /// Clients keyed by whether they're used for HTTPS requests, by whether they skip the
+    /// certificate verification, and by whether they send the absolute-form request target.
+    pub http_clients: Mutex<HashMap<(bool, bool, bool), reqwest::Client>>,
+    pub root_certificates: OnceLock<Vec<reqwest::Certificate>>,
+    pub client_identity: OnceLock<reqwest::Identity>,
+    pub upstream_proxy: OnceLock<reqwest::Proxy>,
+    pub audit_tx: OnceLock<mpsc::Sender<AuditRecord>>,
+    pub disk_cache: OnceLock<DiskCache>,
+    pub hook: OnceLock<Arc<dyn ProxyHook>>,
+    /// BlueZ starts the notifications of a characteristic once for all the subscribed devices,
+    /// so its notifier is handed to every session, the ones started afterwards included.
+    pub subscriptions: Mutex<Notifiers>,
+    pub transferred_bytes: Mutex<HashMap<Address, u64>>,
+    pub request_counts: Mutex<HashMap<Address, u32>>,
+    pub adapter: OnceLock<Adapter>,
 }
 
 impl AppState {
     pub fn new() -> Self {
         Self {
-            http_uri: Arc::new(Mutex::new(Vec::new())),
-            http_headers: Arc::new(Mutex::new(Vec::new())),
-            http_status_code: Arc::new(Mutex::new(Vec::new())),
-            http_entity_body: Arc::new(Mutex::new(Vec::new())),
-            https_security: Arc::new(Mutex::new(Vec::new())),
-            http_headers_body_chunk_idx: Arc::new(Mutex::new(vec![0; 8])),
-            http_headers_body_sizes: Arc::new(Mutex::new(Vec::new())),
+            sessions: Mutex::new(HashMap::new()),
+            status_updates: broadcast::channel(STATUS_UPDATES_CAPACITY).0,
+            adapter_info: Arc::new(RwLock::new(Vec::new())),
+            ready: AtomicBool::new(false),
+            shutdown: CancellationToken::new(),
+            metrics: Metrics::default(),
+            diagnostics: Diagnostics::default(),
+            preflight_cache: Mutex::new(HashMap::new()),
+            host_queues: Mutex::new(HashMap::new()),
+            http_clients: Mutex::new(HashMap::new()),
+            root_certificates: OnceLock::new(),
+            client_identity: OnceLock::new(),
+            upstream_proxy: OnceLock::new(),
+            audit_tx: OnceLock::new(),
+            disk_cache: OnceLock::new(),
+            hook: OnceLock::new(),
+            subscriptions: Mutex::new(Notifiers::default()),
+            transferred_bytes: Mutex::new(HashMap::new()),
+            request_counts: Mutex::new(HashMap::new()),
+            adapter: OnceLock::new(),
+        }
+    }
+
+    /// Hook invoked around the proxied requests, a no-op one unless set.
+    pub fn hook(&self) -> &dyn ProxyHook {
+        self.hook.get().map(|hook| hook.as_ref()).unwrap_or(&NoopHook)
+    }
+
+    /// Returns the session of `device`, starting a new one if it has none.
+    pub async fn session(&self, device: Address) -> Arc<Session> {
+        let mut sessions = self.sessions.lock().await;
+        if let Some(session) = sessions.get(&device) {
+            return session.clone();
+        }
+        let notifiers = self.subscriptions.lock().await.clone();
+        let session = Arc::new(Session::new(device, self.status_updates.clone(), notifiers));
+        sessions.insert(device, session.clone());
+        session
+    }
+
+    /// Hands a new notifier to every session, through `set`.
+    pub async fn subscribe(&self, set: impl Fn(&mut Notifiers)) {
+        set(&mut *self.subscriptions.lock().await);
+        for session in self.sessions.lock().await.values() {
+            set(&mut *session.notifiers.lock().await);
         }
     }
-}
\ No newline at end of file
+
+    /// Drops a notifier which failed from `session` and from the subscriptions, unless the client
+    /// subscribed again in the meantime.
+    pub async fn unsubscribe(&self, session: &Session, notifier: &SharedNotifier) {
+        for notifiers in [&self.subscriptions, &session.notifiers] {
+            notifiers.lock().await.remove(notifier);
+        }
+    }
+
+    pub fn summary(&self, started: Instant) -> RunSummary {
+        RunSummary {
+            requests: self.metrics.requests.load(Ordering::Relaxed),
+            errors: self.metrics.errors.load(Ordering::Relaxed),
+            uptime: started.elapsed(),
+        }
+    }
+
+    pub async fn transferred_bytes(&self, device: Address) -> u64 {
+        self.transferred_bytes.lock().await.get(&device).copied().unwrap_or(0)
+    }
+
+    pub async fn add_transferred_bytes(&self, device: Address, bytes: usize) {
+        *self.transferred_bytes.lock().await.entry(device).or_default() += bytes as u64;
+    }
+
+    /// Counts a new request of `device`, returning its number of requests so far.
+    pub async fn count_request(&self, device: Address) -> u32 {
+        let mut request_counts = self.request_counts.lock().await;
+        let count = request_counts.entry(device).or_default();
+        *count = count.saturating_add(1);
+        *count
+    }
+
+    /// Waits up to `grace` for the requests in flight to end, returning whether they all did.
+    pub async fn drain(&self, grace: Duration) -> bool {
+        let deadline = Instant::now() + grace;
+        loop {
+            let in_flight = self
+                .sessions
+                .lock()
+                .await
+                .values()
+                .filter(|session| session.in_flight.load(Ordering::SeqCst))
+                .count();
+            if in_flight == 0 {
+                return true;
+            }
+            if Instant::now() >= deadline {
+                return false;
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+    }
+
+    /// Drops the state accumulated by `device`, its session included.
+    pub async fn reset_device(&self, device: Address) {
+        self.request_counts.lock().await.remove(&device);
+        self.transferred_bytes.lock().await.remove(&device);
+        self.sessions.lock().await.remove(&device);
+    }
+}
@@ -0,0 +1,40 @@
+use crate::Config;
+use bluer::{gatt::local::ReqError, Address};
+use std::sync::Arc;
+use tracing::warn;
+
+/// Devices allowed to write the characteristics, set by `--allow-device`: every device when none is.
+#[derive(Clone)]
+pub struct DeviceAllowlist(Arc<[Address]>);
+
+impl DeviceAllowlist {
+    pub fn new(config: &Config) -> Self {
+        Self(config.allowed_devices.as_slice().into())
+    }
+
+    pub fn check(&self, device: Address) -> Result<(), ReqError> {
+        if self.0.is_empty() || self.0.contains(&device) {
+            return Ok(());
+        }
+        warn!("Rejecting the write of device {}, which isn't allowed", device);
+        Err(ReqError::NotAuthorized)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_allowed_devices_write() {
+        let allowed = Address::new([0x00, 0x11, 0x22, 0x33, 0x44, 0x55]);
+        let other = Address::new([0x66, 0x77, 0x88, 0x99, 0xaa, 0xbb]);
+
+        let everyone = DeviceAllowlist(Arc::new([]));
+        assert!(everyone.check(other).is_ok());
+
+        let allowlist = DeviceAllowlist(Arc::new([allowed]));
+        assert!(allowlist.check(allowed).is_ok());
+        assert!(matches!(allowlist.check(other), Err(ReqError::NotAuthorized)));
+    }
+}
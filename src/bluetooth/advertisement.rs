@@ -1,18 +1,216 @@
-use crate::{Config, Result};
+use crate::{error::Error, Config, Result};
 use bluer::{
-    Adapter, 
-    adv::{AdvertisementHandle, Advertisement}
+    Adapter, Address,
+    adv::{AdvertisementHandle, Advertisement, Feature, SecondaryChannel}
 };
-use crate::constants::SERVICE_UUID;
+use std::collections::BTreeMap;
+use tracing::{info, warn};
+use super::uuids::Uuids;
 
-pub async fn create_advertisement(adapter: &Adapter, config: &Config) -> Result<AdvertisementHandle> {
-    let le_advertisement = Advertisement {
-        service_uuids: vec![*SERVICE_UUID].into_iter().collect(),
+/// Longest local name fitting in the advertising data.
+const MAX_LOCAL_NAME_SIZE: usize = 29;
+
+/// Range of the TX power in dBm accepted by BlueZ.
+const TX_POWER_RANGE: std::ops::RangeInclusive<i16> = -127..=20;
+
+/// Size of the legacy advertising data, larger payloads need extended advertising.
+const LEGACY_ADVERTISING_DATA_SIZE: usize = 31;
+
+/// The Bluetooth Base UUID, which the 16-bit UUIDs are aliases of.
+const BLUETOOTH_BASE_UUID: u128 = 0x0000_0000_0000_1000_8000_0080_5f9b_34fb;
+
+fn validate_name(name: &str) -> std::result::Result<(), String> {
+    if name.is_empty() {
+        return Err("the name is empty".to_string());
+    }
+    if name.len() > MAX_LOCAL_NAME_SIZE {
+        return Err(format!("the name is longer than {} bytes", MAX_LOCAL_NAME_SIZE));
+    }
+    if name.chars().any(char::is_control) {
+        return Err("the name contains control characters".to_string());
+    }
+    Ok(())
+}
+
+/// Parses bytes written as hexadecimal digits, optionally separated by `:` or spaces
+/// (e.g. `01ab`, `01:ab`).
+fn parse_hex(value: &str) -> std::result::Result<Vec<u8>, String> {
+    let digits: String = value.chars().filter(|c| *c != ':' && !c.is_whitespace()).collect();
+    if digits.len() % 2 != 0 {
+        return Err("odd number of hexadecimal digits".to_string());
+    }
+    (0..digits.len())
+        .step_by(2)
+        .map(|i| {
+            digits
+                .get(i..i + 2)
+                .and_then(|byte| u8::from_str_radix(byte, 16).ok())
+                .ok_or_else(|| format!("invalid byte at position {}", i / 2))
+        })
+        .collect()
+}
+
+/// Size of a UUID in the advertising data: 2 bytes when it's an alias of the Bluetooth Base UUID.
+fn uuid_size(uuid: &uuid::Uuid) -> usize {
+    if uuid.as_u128() & !(0xffff_u128 << 96) == BLUETOOTH_BASE_UUID {
+        2
+    } else {
+        16
+    }
+}
+
+/// Size of the advertising data, each field taking a length and a type byte besides its value.
+/// The local name and the appearance are sent in the scan response instead.
+fn advertising_data_size(advertisement: &Advertisement) -> usize {
+    // Flags
+    let mut size = 3;
+    if !advertisement.service_uuids.is_empty() {
+        size += 2 + advertisement.service_uuids.iter().map(uuid_size).sum::<usize>();
+    }
+    if advertisement.system_includes.contains(&Feature::TxPower) {
+        size += 3;
+    }
+    size += advertisement.manufacturer_data.values().map(|data| 2 + 2 + data.len()).sum::<usize>();
+    size += advertisement
+        .service_data
+        .iter()
+        .map(|(uuid, data)| 2 + uuid_size(uuid) + data.len())
+        .sum::<usize>();
+    size
+}
+
+/// Name advertised in place of an invalid one: the crate name followed by the end of the
+/// adapter address, so that several gateways can still be told apart.
+fn fallback_name(address: Address) -> String {
+    format!("{}-{:02X}{:02X}", env!("CARGO_PKG_NAME"), address.0[4], address.0[5])
+}
+
+/// The configured service name, or `None` when it's invalid and the fallback name is advertised
+/// instead. With `--strict-name`, an invalid name is an error.
+fn configured_name(config: &Config) -> Result<Option<String>> {
+    match validate_name(&config.name) {
+        Ok(()) => Ok(Some(config.name.clone())),
+        Err(reason) if config.strict_name => {
+            Err(Error::Application(format!("Invalid service name '{}': {}", config.name, reason)))
+        }
+        Err(reason) => {
+            warn!("Invalid service name '{}' ({}), advertising the fallback name", config.name, reason);
+            Ok(None)
+        }
+    }
+}
+
+pub async fn create_advertisement(adapter: &Adapter, config: &Config, uuids: &Uuids) -> Result<AdvertisementHandle> {
+    let local_name = match configured_name(config)? {
+        Some(name) => name,
+        None => {
+            let name = fallback_name(adapter.address().await?);
+            info!("Advertising as '{}'", name);
+            name
+        }
+    };
+
+    if let Some(tx_power) = config.tx_power {
+        if !TX_POWER_RANGE.contains(&tx_power) {
+            return Err(Error::Application(format!(
+                "Invalid TX power {} dBm: it must be between {} and {}",
+                tx_power,
+                TX_POWER_RANGE.start(),
+                TX_POWER_RANGE.end()
+            )));
+        }
+        info!("Advertising with a TX power of {} dBm", tx_power);
+    }
+    if let Some(appearance) = config.appearance {
+        info!("Advertising with the appearance 0x{:04X}", appearance);
+    }
+
+    let mut manufacturer_data = BTreeMap::new();
+    if let (Some(id), Some(data)) = (config.manufacturer_id, &config.manufacturer_data) {
+        let data = parse_hex(data)
+            .map_err(|reason| Error::Application(format!("Invalid manufacturer data '{}': {}", data, reason)))?;
+        manufacturer_data.insert(id, data);
+    }
+    let mut service_data = BTreeMap::new();
+    if let Some(data) = &config.service_data {
+        let data = parse_hex(data)
+            .map_err(|reason| Error::Application(format!("Invalid service data '{}': {}", data, reason)))?;
+        service_data.insert(uuids.service(), data);
+    }
+
+    // The TX power is included in the advertising data, so that clients can estimate the distance
+    let mut le_advertisement = Advertisement {
+        service_uuids: vec![uuids.service()].into_iter().collect(),
         discoverable: Some(true),
-        local_name: Some(config.name.clone()),
+        local_name: Some(local_name),
+        tx_power: config.tx_power,
+        appearance: config.appearance,
+        system_includes: config.tx_power.map(|_| Feature::TxPower).into_iter().collect(),
+        manufacturer_data,
+        service_data,
         ..Default::default()
     };
+    let size = advertising_data_size(&le_advertisement);
+    if size > LEGACY_ADVERTISING_DATA_SIZE {
+        warn!(
+            "The advertising data takes {} bytes, more than the {} of legacy advertising: using extended advertising, which older clients can't scan",
+            size, LEGACY_ADVERTISING_DATA_SIZE
+        );
+        le_advertisement.secondary_channel = Some(SecondaryChannel::OneM);
+    }
 
     let handle = adapter.advertise(le_advertisement).await?;
     Ok(handle)
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::constants::SERVICE_UUID;
+    use clap::Parser;
+
+    #[test]
+    fn hex_bytes() {
+        assert_eq!(parse_hex("01ab"), Ok(vec![0x01, 0xab]));
+        assert_eq!(parse_hex("01:AB ff"), Ok(vec![0x01, 0xab, 0xff]));
+        assert_eq!(parse_hex(""), Ok(vec![]));
+        assert!(parse_hex("abc").is_err());
+        assert!(parse_hex("zz").is_err());
+        assert!(parse_hex("é0").is_err());
+    }
+
+    #[test]
+    fn valid_name() {
+        let config = Config::parse_from(["hps-ble", "--name", "Gateway 1"]);
+        assert_eq!(configured_name(&config).unwrap().as_deref(), Some("Gateway 1"));
+    }
+
+    #[test]
+    fn invalid_name_falls_back() {
+        let config = Config::parse_from(["hps-ble", "--name", "A name too long to fit in the advertising data"]);
+        assert_eq!(configured_name(&config).unwrap(), None);
+        assert_eq!(configured_name(&Config::parse_from(["hps-ble", "--name", "HPS\n"])).unwrap(), None);
+        assert_eq!(fallback_name(Address([0, 0, 0, 0, 0xab, 0x01])), format!("{}-AB01", env!("CARGO_PKG_NAME")));
+    }
+
+    #[test]
+    fn invalid_name_in_strict_mode() {
+        let config = Config::parse_from(["hps-ble", "--name", "", "--strict-name"]);
+        assert!(configured_name(&config).is_err());
+    }
+
+    #[test]
+    fn legacy_advertising_data_limit() {
+        let mut advertisement = Advertisement {
+            service_uuids: vec![*SERVICE_UUID].into_iter().collect(),
+            system_includes: [Feature::TxPower].into_iter().collect(),
+            manufacturer_data: BTreeMap::from([(0xffff, vec![0; 10])]),
+            ..Default::default()
+        };
+        // Flags, 16-bit service UUID, TX power and manufacturer data
+        assert_eq!(advertising_data_size(&advertisement), 3 + 4 + 3 + 14);
+
+        advertisement.service_data = BTreeMap::from([(*SERVICE_UUID, vec![0; 4])]);
+        assert!(advertising_data_size(&advertisement) > LEGACY_ADVERTISING_DATA_SIZE);
+    }
+}
@@ -1,26 +1,131 @@
 use crate::{AppState, Config};
-use bluer::gatt::local::{Application, Service};
+use bluer::gatt::local::{Application, Characteristic, Service};
 use std::sync::Arc;
-use crate::constants::SERVICE_UUID;
-use super::characteristics;
+use uuid::Uuid;
+use crate::constants::{
+    ADAPTER_INFO_UUID, HEADERS_CHANGED_UUID, HTTPS_SECURITY_UUID, HTTP_CONTROL_POINT_UUID, HTTP_ENTITY_BODY_UUID, HTTP_HEADERS_BODY_CHUNK_IDX_UUID,
+    HTTP_HEADERS_BODY_SIZES_UUID, HTTP_HEADERS_UUID, HTTP_STATUS_CODE_UUID, HTTP_URI_UUID, METRICS_UUID, NEGOTIATED_MTU_UUID, REQUEST_TIMING_UUID, BASIC_AUTH_UUID, DIAGNOSTICS_UUID,
+};
+use super::{characteristics, uuids::Uuids};
+
+/// Description of a characteristic exposed by the GATT application.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CharacteristicDescriptor {
+    pub uuid: Uuid,
+    pub name: &'static str,
+    pub read: bool,
+    pub write: bool,
+    pub write_without_response: bool,
+    pub notify: bool,
+}
+
+impl CharacteristicDescriptor {
+    fn from_characteristic(characteristic: &Characteristic, uuids: &Uuids) -> Self {
+        Self {
+            uuid: characteristic.uuid,
+            name: characteristic_name(&uuids.default_of(characteristic.uuid)),
+            read: characteristic.read.as_ref().is_some_and(|read| read.read),
+            write: characteristic.write.as_ref().is_some_and(|write| write.write),
+            write_without_response: characteristic.write.as_ref().is_some_and(|write| write.write_without_response),
+            notify: characteristic.notify.as_ref().is_some_and(|notify| notify.notify),
+        }
+    }
+}
+
+fn characteristic_name(uuid: &Uuid) -> &'static str {
+    [
+        (*HTTP_HEADERS_BODY_SIZES_UUID, "HTTP Headers Body MTU Sizes"),
+        (*HTTP_HEADERS_BODY_CHUNK_IDX_UUID, "HTTP Headers Body Chunk Index"),
+        (*HTTP_URI_UUID, "HTTP URI"),
+        (*HTTP_HEADERS_UUID, "HTTP Headers"),
+        (*HTTP_STATUS_CODE_UUID, "HTTP Status Code"),
+        (*HTTP_ENTITY_BODY_UUID, "HTTP Entity Body"),
+        (*HTTPS_SECURITY_UUID, "HTTPS Security"),
+        (*HTTP_CONTROL_POINT_UUID, "HTTP Control Point"),
+        (*ADAPTER_INFO_UUID, "Adapter Info"),
+        (*METRICS_UUID, "Metrics"),
+        (*HEADERS_CHANGED_UUID, "Headers Changed"),
+        (*REQUEST_TIMING_UUID, "Request Timing"),
+        (*NEGOTIATED_MTU_UUID, "Negotiated MTU"),
+        (*BASIC_AUTH_UUID, "Basic Auth"),
+        (*DIAGNOSTICS_UUID, "Diagnostics"),
+    ]
+    .into_iter()
+    .find(|(known, _)| known == uuid)
+    .map(|(_, name)| name)
+    .unwrap_or("Unknown")
+}
+
+pub fn create_application(state: &Arc<AppState>, config: &Config, uuids: &Uuids) -> Application {
+    let mut characteristics = vec![
+        characteristics::create_headers_body_mtu_sizes(state),
+        characteristics::create_headers_body_chunk_idx(state, config),
+        characteristics::create_http_uri(state, config),
+        characteristics::create_http_headers(state, config),
+        characteristics::create_http_status_code(state, config),
+        characteristics::create_http_entity_body(state, config),
+        characteristics::create_https_security(state),
+        characteristics::create_http_control_point(state, config),
+        characteristics::create_adapter_info(state),
+        characteristics::create_request_timing(state),
+        characteristics::create_negotiated_mtu(),
+        characteristics::create_basic_auth(state, config),
+        characteristics::create_diagnostics(state, config),
+    ];
+    if config.metrics {
+        characteristics.push(characteristics::create_metrics(state, config));
+    }
+    if config.headers_change_notify {
+        characteristics.push(characteristics::create_headers_changed(state));
+    }
+    for characteristic in characteristics.iter_mut() {
+        characteristic.uuid = uuids.get(characteristic.uuid);
+    }
 
-pub fn create_application(state: &Arc<AppState>, config: &Config) -> Application {
     Application {
         services: vec![Service {
-            uuid: *SERVICE_UUID,
+            uuid: uuids.service(),
             primary: true,
-            characteristics: vec![
-                characteristics::create_headers_body_mtu_sizes(state),
-                characteristics::create_headers_body_chunk_idx(state),
-                characteristics::create_http_uri(state),
-                characteristics::create_http_headers(state, config),
-                characteristics::create_http_status_code(state),
-                characteristics::create_http_entity_body(state, config),
-                characteristics::create_https_security(state),
-                characteristics::create_http_control_point(state, config),
-            ],
+            characteristics,
             ..Default::default()
         }],
         ..Default::default()
     }
-}
\ No newline at end of file
+}
+
+/// Describes the characteristics served for `config`.
+///
+/// The description is taken from the application that `create_application` builds,
+/// so it cannot drift from what is actually registered with BlueZ.
+pub fn describe_profile(config: &Config) -> Vec<CharacteristicDescriptor> {
+    let state = Arc::new(AppState::new());
+    let uuids = Uuids::new(&config.uuids);
+    create_application(&state, config, &uuids)
+        .services
+        .iter()
+        .flat_map(|service| service.characteristics.iter())
+        .map(|characteristic| CharacteristicDescriptor::from_characteristic(characteristic, &uuids))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+    use std::collections::HashSet;
+
+    #[test]
+    fn every_characteristic_is_listed_once() {
+        let config = Config::parse_from(["hps-ble", "--metrics", "--headers-change-notify"]);
+        let profile = describe_profile(&config);
+        let uuids: HashSet<Uuid> = profile.iter().map(|characteristic| characteristic.uuid).collect();
+        assert_eq!(uuids.len(), profile.len());
+        assert_eq!(profile.len(), 15);
+        assert!(profile.iter().all(|characteristic| characteristic.name != "Unknown"));
+
+        // The optional characteristics are only listed when enabled
+        let profile = describe_profile(&Config::parse_from(["hps-ble"]));
+        assert_eq!(profile.len(), 13);
+        assert!(!profile.iter().any(|characteristic| [*METRICS_UUID, *HEADERS_CHANGED_UUID].contains(&characteristic.uuid)));
+    }
+}
@@ -0,0 +1,27 @@
+use crate::AppState;
+use bluer::gatt::local::{Characteristic, CharacteristicRead};
+use futures::FutureExt;
+use std::sync::Arc;
+use tracing::debug;
+use crate::constants::ADAPTER_INFO_UUID;
+
+pub fn create_characteristic(state: &Arc<AppState>) -> Characteristic {
+    let state_r = state.clone();
+    Characteristic {
+        uuid: *ADAPTER_INFO_UUID,
+        read: Some(CharacteristicRead {
+            read: true,
+            fun: Box::new(move |req| {
+                let value = state_r.adapter_info.clone();
+                async move {
+                    let value = value.read().await.clone();
+                    debug!(target: "adapter_info", "Read request {:?} with value {:x?}", &req, &value);
+                    Ok(value)
+                }
+                .boxed()
+            }),
+            ..Default::default()
+        }),
+        ..Default::default()
+    }
+}
\ No newline at end of file
@@ -0,0 +1,38 @@
+use crate::{bluetooth::access, config::WriteSecurity, AppState, Config};
+use bluer::gatt::local::{Characteristic, CharacteristicWrite, CharacteristicWriteMethod};
+use futures::FutureExt;
+use std::sync::Arc;
+use tracing::debug;
+use zeroize::Zeroizing;
+use crate::constants::BASIC_AUTH_UUID;
+
+/// Write-only, so that the credentials are never read back.
+pub fn create_characteristic(state: &Arc<AppState>, config: &Config) -> Characteristic {
+    let allowlist = access::DeviceAllowlist::new(config);
+    let state_w = state.clone();
+    Characteristic {
+        uuid: *BASIC_AUTH_UUID,
+        write: Some(CharacteristicWrite {
+            write: true,
+            write_without_response: !config.require_write_response,
+            encrypt_write: config.write_security == WriteSecurity::Encrypted,
+            encrypt_authenticated_write: config.write_security == WriteSecurity::Authenticated,
+            method: CharacteristicWriteMethod::Fun(Box::new(move |new_value, req| {
+                let state = state_w.clone();
+                let allowlist = allowlist.clone();
+                let new_value = Zeroizing::new(new_value);
+                async move {
+                    allowlist.check(req.device_address)?;
+                    debug!(target: "basic_auth", "Write request {:?} with {} bytes", &req, new_value.len());
+                    let session = state.session(req.device_address).await;
+                    // The previous credentials are zeroized when replaced
+                    *session.basic_auth.lock().await = new_value;
+                    Ok(())
+                }
+                .boxed()
+            })),
+            ..Default::default()
+        }),
+        ..Default::default()
+    }
+}
@@ -0,0 +1,52 @@
+use crate::{bluetooth::access, config::WriteSecurity, AppState, Config};
+use bluer::gatt::local::{Characteristic, CharacteristicRead, CharacteristicWrite, CharacteristicWriteMethod, ReqError};
+use futures::FutureExt;
+use std::sync::Arc;
+use tracing::debug;
+use crate::constants::DIAGNOSTICS_UUID;
+
+/// Value to write to reset the counters.
+const RESET_OPCODE: u8 = 1;
+
+pub fn create_characteristic(state: &Arc<AppState>, config: &Config) -> Characteristic {
+    let allowlist = access::DeviceAllowlist::new(config);
+    let state_r = state.clone();
+    let state_w = state.clone();
+    Characteristic {
+        uuid: *DIAGNOSTICS_UUID,
+        read: Some(CharacteristicRead {
+            read: true,
+            fun: Box::new(move |req| {
+                let value = state_r.diagnostics.encode();
+                async move {
+                    debug!(target: "diagnostics", "Read request {:?} with value {:x?}", &req, &value);
+                    Ok(value)
+                }
+                .boxed()
+            }),
+            ..Default::default()
+        }),
+        write: Some(CharacteristicWrite {
+            write: true,
+            encrypt_write: config.write_security == WriteSecurity::Encrypted,
+            encrypt_authenticated_write: config.write_security == WriteSecurity::Authenticated,
+            method: CharacteristicWriteMethod::Fun(Box::new(move |new_value, req| {
+                let state = state_w.clone();
+                let allowlist = allowlist.clone();
+                async move {
+                    allowlist.check(req.device_address)?;
+                    debug!(target: "diagnostics", "Write request {:?} with value {:x?}", &req, &new_value);
+                    if new_value != [RESET_OPCODE] {
+                        return Err(ReqError::NotSupported);
+                    }
+                    state.diagnostics.reset();
+                    debug!(target: "diagnostics", "Counters reset");
+                    Ok(())
+                }
+                .boxed()
+            })),
+            ..Default::default()
+        }),
+        ..Default::default()
+    }
+}
@@ -1,11 +1,12 @@
-use crate::AppState;
-use bluer::gatt::local::{Characteristic, CharacteristicRead, CharacteristicWrite, CharacteristicWriteMethod};
+use crate::{bluetooth::access, config::WriteSecurity, utils, AppState, Config};
+use bluer::gatt::local::{Characteristic, CharacteristicRead, CharacteristicWrite, CharacteristicWriteMethod, ReqError};
 use futures::FutureExt;
 use std::sync::Arc;
-use tracing::debug;
+use tracing::{debug, warn};
 use crate::constants::HTTP_HEADERS_BODY_CHUNK_IDX_UUID;
 
-pub fn create_characteristic(state: &Arc<AppState>) -> Characteristic {
+pub fn create_characteristic(state: &Arc<AppState>, config: &Config) -> Characteristic {
+    let allowlist = access::DeviceAllowlist::new(config);
     let state_r = state.clone();
     let state_w = state.clone();
     Characteristic {
@@ -13,9 +14,9 @@ pub fn create_characteristic(state: &Arc<AppState>) -> Characteristic {
         read: Some(CharacteristicRead {
             read: true,
             fun: Box::new(move |req| {
-                let value = state_r.http_headers_body_chunk_idx.clone();
+                let state = state_r.clone();
                 async move {
-                    let value = value.lock().await.clone();
+                    let value = state.session(req.device_address).await.http_headers_body_chunk_idx.read().await.clone();
                     debug!(target: "headers_body_chunk_idx", "Read request {:?} with value {:x?}", &req, &value);
                     Ok(value)
                 }
@@ -25,12 +26,21 @@ pub fn create_characteristic(state: &Arc<AppState>) -> Characteristic {
         }),
         write: Some(CharacteristicWrite {
             write: true,
-            write_without_response: true,
+            write_without_response: !config.require_write_response,
+            encrypt_write: config.write_security == WriteSecurity::Encrypted,
+            encrypt_authenticated_write: config.write_security == WriteSecurity::Authenticated,
             method: CharacteristicWriteMethod::Fun(Box::new(move |new_value, req| {
-                let value = state_w.http_headers_body_chunk_idx.clone();
+                let state = state_w.clone();
+                let allowlist = allowlist.clone();
                 async move {
+                    allowlist.check(req.device_address)?;
                     debug!(target: "headers_body_chunk_idx", "Write request {:?} with value {:x?}", &req, &new_value);
-                    let mut value = value.lock().await;
+                    if !utils::is_valid_chunk_indexes(&new_value) {
+                        warn!(target: "headers_body_chunk_idx", "Rejecting chunk indexes of {} bytes, both indexes are required", new_value.len());
+                        return Err(ReqError::InvalidValueLength);
+                    }
+                    let session = state.session(req.device_address).await;
+                    let mut value = session.http_headers_body_chunk_idx.write().await;
                     *value = new_value;
                     Ok(())
                 }
@@ -1,10 +1,16 @@
-use crate::AppState;
+use crate::{http, AppState};
 use bluer::gatt::local::{Characteristic, CharacteristicRead};
+use byteorder::{LittleEndian, WriteBytesExt};
 use futures::FutureExt;
 use std::sync::Arc;
 use tracing::debug;
 use crate::constants::HTTP_HEADERS_BODY_SIZES_UUID;
 
+/// Size of the response headers, body and chunk sizes, the chunk counts follow them.
+const RESPONSE_SIZES_SIZE: usize = 12;
+/// Size of the response headers and body chunk counts, the checksums (if any) follow them.
+const CHUNK_COUNTS_SIZE: usize = 8;
+
 pub fn create_characteristic(state: &Arc<AppState>) -> Characteristic {
     let state = state.clone();
     Characteristic {
@@ -12,9 +18,23 @@ pub fn create_characteristic(state: &Arc<AppState>) -> Characteristic {
         read: Some(CharacteristicRead {
             read: true,
             fun: Box::new(move |req| {
-                let value = state.http_headers_body_sizes.clone();
+                let state = state.clone();
                 async move {
-                    let value = value.lock().await.clone();
+                    let session = state.session(req.device_address).await;
+                    // The lengths of the body and headers written so far, and the negotiated MTU, go
+                    // between the response sizes and the chunk counts, the checksums are always last
+                    let mut value = session.http_headers_body_sizes.read().await.clone();
+                    value.resize(value.len().max(RESPONSE_SIZES_SIZE + CHUNK_COUNTS_SIZE), 0);
+                    let mut chunk_counts = value.split_off(RESPONSE_SIZES_SIZE);
+                    let checksums = chunk_counts.split_off(CHUNK_COUNTS_SIZE);
+                    let body_len = http::spool::body_len(&session).await;
+                    let headers_len = session.request_headers.read().await.len() as u64;
+                    for len in [body_len, headers_len] {
+                        value.write_u32::<LittleEndian>(len.min(u32::MAX as u64) as u32).unwrap();
+                    }
+                    value.write_u32::<LittleEndian>(req.mtu as u32).unwrap();
+                    value.extend(chunk_counts);
+                    value.extend(checksums);
                     debug!(target: "headers_body_mtu_sizes", "Read request {:?} with value {:x?}", &req, &value);
                     Ok(value)
                 }
@@ -24,4 +44,4 @@ pub fn create_characteristic(state: &Arc<AppState>) -> Characteristic {
         }),
         ..Default::default()
     }
-}
\ No newline at end of file
+}
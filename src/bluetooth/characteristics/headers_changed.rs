@@ -0,0 +1,42 @@
+use crate::AppState;
+use bluer::gatt::local::{Characteristic, CharacteristicNotify, CharacteristicNotifyMethod, CharacteristicRead};
+use futures::FutureExt;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use tracing::debug;
+use crate::constants::HEADERS_CHANGED_UUID;
+
+pub fn create_characteristic(state: &Arc<AppState>) -> Characteristic {
+    let state_r = state.clone();
+    let state_n = state.clone();
+    Characteristic {
+        uuid: *HEADERS_CHANGED_UUID,
+        read: Some(CharacteristicRead {
+            read: true,
+            fun: Box::new(move |req| {
+                let state = state_r.clone();
+                async move {
+                    let value = state.session(req.device_address).await.headers_changed.read().await.clone();
+                    debug!(target: "headers_changed", "Read request {:?} with value {:x?}", &req, &value);
+                    Ok(value)
+                }
+                .boxed()
+            }),
+            ..Default::default()
+        }),
+        notify: Some(CharacteristicNotify {
+            notify: true,
+            method: CharacteristicNotifyMethod::Fun(Box::new(move |notifier| {
+                let state = state_n.clone();
+                async move {
+                    debug!(target: "headers_changed", "Client subscribed to the headers change notifications");
+                    let notifier = Arc::new(Mutex::new(notifier));
+                    state.subscribe(|notifiers| notifiers.headers_changed = Some(notifier.clone())).await;
+                }
+                .boxed()
+            })),
+            ..Default::default()
+        }),
+        ..Default::default()
+    }
+}
@@ -1,4 +1,4 @@
-use crate::{http, AppState, Config};
+use crate::{bluetooth::access, config::WriteSecurity, http, AppState, Config};
 use bluer::gatt::local::{Characteristic, CharacteristicWrite, CharacteristicWriteMethod};
 use futures::FutureExt;
 use std::sync::Arc;
@@ -6,25 +6,29 @@ use tracing::debug;
 use crate::constants::HTTP_CONTROL_POINT_UUID;
 
 pub fn create_characteristic(state: &Arc<AppState>, config: &Config) -> Characteristic {
+    let allowlist = access::DeviceAllowlist::new(config);
     let state_r = state.clone();
     let config = config.clone();
     Characteristic {
         uuid: *HTTP_CONTROL_POINT_UUID,
         write: Some(CharacteristicWrite {
             write: true,
-            write_without_response: true,
+            write_without_response: !config.require_write_response,
+            encrypt_write: config.write_security == WriteSecurity::Encrypted,
+            encrypt_authenticated_write: config.write_security == WriteSecurity::Authenticated,
             method: CharacteristicWriteMethod::Fun(Box::new(move |new_value, req| {
                 let state = state_r.clone();
+                let allowlist = allowlist.clone();
                 let config = config.clone();
                 async move {
+                    allowlist.check(req.device_address)?;
                     debug!(target: "http_control_point", "Write request {:?} with value {:x?}", &req, &new_value);
                     let mtu = config.effective_mtu(req.mtu as usize);
-                    let timeout = config.timeout_duration();
                     let _ = http::handler::handle_http_control_point(
                         &state,
                         new_value,
-                        req,
-                        timeout,
+                        req.device_address,
+                        &config,
                         mtu
                     ).await;
                     Ok(())
@@ -1,40 +1,56 @@
-use crate::{AppState, Config, utils};
-use bluer::gatt::local::{Characteristic, CharacteristicRead, CharacteristicWrite, CharacteristicWriteMethod};
+use crate::{bluetooth::access, config::WriteSecurity, http, logging, AppState, Config, utils};
+use bluer::gatt::local::{Characteristic, CharacteristicNotify, CharacteristicNotifyMethod, CharacteristicRead, CharacteristicWrite, CharacteristicWriteMethod, ReqError};
 use futures::FutureExt;
-use std::sync::Arc;
-use tracing::debug;
+use std::sync::{atomic::Ordering, Arc};
+use tokio::sync::Mutex;
+use tracing::{debug, warn};
 use crate::constants::HTTP_ENTITY_BODY_UUID;
 
 pub fn create_characteristic(state: &Arc<AppState>, config: &Config) -> Characteristic {
+    let allowlist = access::DeviceAllowlist::new(config);
     let state_r = state.clone();
     let state_w = state.clone();
+    let state_n = state.clone();
+    let write_without_response = !config.require_write_response;
     let config = config.clone();
+    let config_w = config.clone();
     Characteristic {
         uuid: *HTTP_ENTITY_BODY_UUID,
         read: Some(CharacteristicRead {
             read: true,
             fun: Box::new(move |req| {
-                let value = state_r.http_entity_body.clone();
-                let body_idx = state_r.http_headers_body_chunk_idx.clone();
                 let effective_mtu = config.effective_mtu(req.mtu as usize);
+                let empty_body_before_request = config.empty_body_before_request;
+                let report_chunk_overrun = config.report_chunk_overrun;
+                let config = config.clone();
+                let state = state_r.clone();
                 async move {
-                    let value = value.lock().await;
-                    let body_idx = body_idx.lock().await;
+                    let session = state.session(req.device_address).await;
+                    let no_request_yet = session.request_generation.load(Ordering::SeqCst) == 0;
+                    if no_request_yet && empty_body_before_request {
+                        debug!(target: "http_entity_body", "Read request {:?} before any completed request, returning an empty chunk", &req);
+                        return Ok(Vec::new());
+                    }
+
+                    // Chunks share the memory of the value, which stays locked only while it is cloned
+                    let value = session.response_body.read().await.clone();
+                    let body_idx = session.http_headers_body_chunk_idx.read().await;
+                    let effective_mtu = utils::get_chunk_size(&body_idx, effective_mtu);
                     
-                    let chunk_index = utils::get_chunk_index(&body_idx, false).unwrap();
+                    let chunk_index = utils::get_chunk_index(&body_idx, false).map_err(|_| ReqError::Failed)?;
                     let total_len = value.len();
                     
-                    let start = chunk_index * effective_mtu;
-                    let end = (start + effective_mtu).min(total_len);
-                    
-                    let chunk = if start < total_len {
-                        value[start..end].to_vec()
-                    } else {
-                        Vec::new()
-                    };
+                    let start = chunk_index.saturating_mul(effective_mtu);
+                    let chunk = utils::get_chunk_bytes(&value, chunk_index, effective_mtu);
+                    if chunk_index > 0 && start >= total_len {
+                        warn!(target: "http_entity_body", "Chunk {} is past the end of the data ({} chunks)", chunk_index, utils::get_chunk_count(total_len, effective_mtu));
+                        if report_chunk_overrun {
+                            session.mark_chunk_overrun().await;
+                        }
+                    }
                     
-                    debug!(target: "http_entity_body", "Read request {:?} with chunk {:x?} (index: {}, start: {}, end: {})", &req, &chunk, chunk_index, start, end);
-                    Ok(chunk)
+                    debug!(target: "http_entity_body", "Read request {:?} with a chunk of {} bytes starting with {:x?} (index: {}, start: {})", &req, chunk.len(), logging::body_prefix(&config, &chunk), chunk_index, start);
+                    Ok(chunk.to_vec())
                 }
                 .boxed()
             }),
@@ -42,14 +58,36 @@ pub fn create_characteristic(state: &Arc<AppState>, config: &Config) -> Characte
         }),
         write: Some(CharacteristicWrite {
             write: true,
-            write_without_response: true,
+            write_without_response,
+            encrypt_write: config.write_security == WriteSecurity::Encrypted,
+            encrypt_authenticated_write: config.write_security == WriteSecurity::Authenticated,
             method: CharacteristicWriteMethod::Fun(Box::new(move |new_value, req| {
-                let value = state_w.http_entity_body.clone();
+                let state = state_w.clone();
+                let allowlist = allowlist.clone();
+                let config = config_w.clone();
+                async move {
+                    allowlist.check(req.device_address)?;
+                    debug!(target: "http_entity_body", "Write request {:?} with {} bytes starting with {:x?}", &req, new_value.len(), logging::body_prefix(&config, &new_value));
+                    let session = state.session(req.device_address).await;
+                    // Writes at chunk index 0 start a new body, writes at later indexes append to it
+                    let chunk_index = utils::get_chunk_index(&session.http_headers_body_chunk_idx.read().await, false).map_err(|_| ReqError::Failed)?;
+                    http::spool::write_body_chunk(&session, &config, chunk_index, new_value).await.map_err(|err| {
+                        warn!(target: "http_entity_body", "Unable to assemble the body: {}", err);
+                        ReqError::Failed
+                    })
+                }
+                .boxed()
+            })),
+            ..Default::default()
+        }),
+        notify: Some(CharacteristicNotify {
+            notify: true,
+            method: CharacteristicNotifyMethod::Fun(Box::new(move |notifier| {
+                let state = state_n.clone();
                 async move {
-                    debug!(target: "http_entity_body", "Write request {:?} with value {:x?}", &req, &new_value);
-                    let mut value = value.lock().await;
-                    *value = new_value;
-                    Ok(())
+                    debug!(target: "http_entity_body", "Client subscribed to the body notifications");
+                    let notifier = Arc::new(Mutex::new(notifier));
+                    state.subscribe(|notifiers| notifiers.body = Some(notifier.clone())).await;
                 }
                 .boxed()
             })),
@@ -1,39 +1,50 @@
-use crate::{AppState, Config, constants::HTTP_HEADERS_UUID, utils};
-use bluer::gatt::local::{Characteristic, CharacteristicRead, CharacteristicWrite, CharacteristicWriteMethod};
+use crate::{bluetooth::access, config::WriteSecurity, AppState, Config, constants::HTTP_HEADERS_UUID, utils};
+use bluer::gatt::local::{Characteristic, CharacteristicRead, CharacteristicWrite, CharacteristicWriteMethod, ReqError};
 use futures::FutureExt;
 use std::sync::Arc;
-use tracing::debug;
+use tracing::{debug, warn};
 
 pub fn create_characteristic(state: &Arc<AppState>, config: &Config) -> Characteristic {
+    let allowlist = access::DeviceAllowlist::new(config);
     let state_r = state.clone();
     let state_w = state.clone();
+    let write_without_response = !config.require_write_response;
+    let log_unredacted = config.log_unredacted;
     let config = config.clone();
     Characteristic {
         uuid: *HTTP_HEADERS_UUID,
         read: Some(CharacteristicRead {
             read: true,
             fun: Box::new(move |req| {
-                let value = state_r.http_headers.clone();
-                let headers_idx = state_r.http_headers_body_chunk_idx.clone();
                 let effective_mtu = config.effective_mtu(req.mtu as usize);
+                let report_chunk_overrun = config.report_chunk_overrun;
+                let state = state_r.clone();
                 async move {
-                    let value = value.lock().await;
-                    let headers_idx = headers_idx.lock().await;
+                    let session = state.session(req.device_address).await;
+                    // Chunks share the memory of the value, which stays locked only while it is cloned
+                    let value = session.response_headers.read().await.clone();
+                    let headers_idx = session.http_headers_body_chunk_idx.read().await;
+                    let effective_mtu = utils::get_chunk_size(&headers_idx, effective_mtu);
                     
-                    let chunk_index = utils::get_chunk_index(&headers_idx, true).unwrap();
+                    let chunk_index = utils::get_chunk_index(&headers_idx, true).map_err(|_| ReqError::Failed)?;
                     let total_len = value.len();
                     
-                    let start = chunk_index * effective_mtu;
-                    let end = (start + effective_mtu).min(total_len);
+                    let start = chunk_index.saturating_mul(effective_mtu);
+                    let chunk = utils::get_chunk_bytes(&value, chunk_index, effective_mtu);
+                    if chunk_index > 0 && start >= total_len {
+                        warn!(target: "http_headers", "Chunk {} is past the end of the data ({} chunks)", chunk_index, utils::get_chunk_count(total_len, effective_mtu));
+                        if report_chunk_overrun {
+                            session.mark_chunk_overrun().await;
+                        }
+                    }
                     
-                    let chunk = if start < total_len {
-                        value[start..end].to_vec()
+                    // Raw headers can't be redacted, only their size is logged
+                    if log_unredacted {
+                        debug!(target: "http_headers", "Read request {:?} with chunk {:x?} (index: {}, start: {})", &req, &chunk[..], chunk_index, start);
                     } else {
-                        Vec::new()
-                    };
-                    
-                    debug!(target: "http_headers", "Read request {:?} with chunk {:x?} (index: {}, start: {}, end: {})", &req, &chunk, chunk_index, start, end);
-                    Ok(chunk)
+                        debug!(target: "http_headers", "Read request {:?} with a chunk of {} bytes (index: {}, start: {})", &req, chunk.len(), chunk_index, start);
+                    }
+                    Ok(chunk.to_vec())
                 }
                 .boxed()
             }),
@@ -41,13 +52,29 @@ pub fn create_characteristic(state: &Arc<AppState>, config: &Config) -> Characte
         }),
         write: Some(CharacteristicWrite {
             write: true,
-            write_without_response: true,
+            write_without_response,
+            encrypt_write: config.write_security == WriteSecurity::Encrypted,
+            encrypt_authenticated_write: config.write_security == WriteSecurity::Authenticated,
             method: CharacteristicWriteMethod::Fun(Box::new(move |new_value, req| {
-                let value = state_w.http_headers.clone();
+                let state = state_w.clone();
+                let allowlist = allowlist.clone();
                 async move {
-                    debug!(target: "http_headers", "Write request {:?} with value {:x?}", &req, &new_value);
-                    let mut value = value.lock().await;
-                    *value = new_value;
+                    allowlist.check(req.device_address)?;
+                    if log_unredacted {
+                        debug!(target: "http_headers", "Write request {:?} with value {:x?}", &req, &new_value);
+                    } else {
+                        debug!(target: "http_headers", "Write request {:?} with {} bytes", &req, new_value.len());
+                    }
+                    let session = state.session(req.device_address).await;
+                    // Writes at chunk index 0 start new headers, writes at later indexes append to them
+                    let chunk_index = utils::get_chunk_index(&session.http_headers_body_chunk_idx.read().await, true).map_err(|_| ReqError::Failed)?;
+                    let mut value = session.request_headers.write().await;
+                    if chunk_index == 0 {
+                        *value = new_value;
+                    } else {
+                        value.extend_from_slice(&new_value);
+                    }
+                    debug!(target: "http_headers", "Assembled headers of {} bytes", value.len());
                     Ok(())
                 }
                 .boxed()
@@ -1,21 +1,50 @@
-use crate::{constants::{EVENT_EMITTER, HTTP_STATUS_CODE_UPDATED_EVENT, HTTP_STATUS_CODE_UUID}, AppState};
-use bluer::gatt::local::{Characteristic, CharacteristicRead, CharacteristicNotify, CharacteristicNotifyMethod};
+use crate::{app_state::StatusUpdate, bluetooth::connections, constants::HTTP_STATUS_CODE_UUID, AppState, Config};
+use bluer::{gatt::local::{Characteristic, CharacteristicRead, CharacteristicNotify, CharacteristicNotifyMethod}, Address};
 use futures::FutureExt;
-use std::sync::Arc;
-use tokio::sync::Mutex;
+use std::{future::Future, sync::Arc};
+use tokio::{sync::{broadcast::{self, error::RecvError}, Mutex}, task::JoinHandle};
 use tracing::{debug, warn};
 
-pub fn create_characteristic(state: &Arc<AppState>) -> Characteristic {
+/// Forwards each status update of `device` to `notify`, or every update when the device isn't
+/// known, until the channel is closed or a notification fails. The subscription to the updates
+/// is dropped along with the forwarding.
+async fn forward_updates<F, Fut>(mut updates: broadcast::Receiver<StatusUpdate>, device: Option<Address>, mut notify: F)
+where
+    F: FnMut(Vec<u8>) -> Fut,
+    Fut: Future<Output = std::io::Result<()>>,
+{
+    loop {
+        match updates.recv().await {
+            Ok(update) if device.is_some_and(|device| device != update.device) => {
+                debug!("Skipping the status update of device {}", update.device);
+            }
+            Ok(update) => {
+                debug!("Notifying with value {:x?}", &update.value);
+                if let Err(err) = notify(update.value).await {
+                    warn!("Notification error: {}", &err);
+                    return;
+                }
+                debug!("Notification sent");
+            }
+            Err(RecvError::Lagged(skipped)) => warn!("Notifications lagging behind, skipped {} status updates", skipped),
+            Err(RecvError::Closed) => return,
+        }
+    }
+}
+
+pub fn create_characteristic(state: &Arc<AppState>, config: &Config) -> Characteristic {
     let state_r = state.clone();
-    let event_listeners_queue = Arc::new(Mutex::new(Vec::<String>::new()));
+    let state_n = state.clone();
+    let cancel_on_disconnect = config.cancel_notify_on_disconnect;
+    let notify_task = Arc::new(Mutex::new(None::<JoinHandle<()>>));
     Characteristic {
         uuid: *HTTP_STATUS_CODE_UUID,
         read: Some(CharacteristicRead {
             read: true,
             fun: Box::new(move |req| {
-                let value = state_r.http_status_code.clone();
+                let state = state_r.clone();
                 async move {
-                    let value = value.lock().await.clone();
+                    let value = state.session(req.device_address).await.http_status_code.read().await.clone();
                     debug!(target: "http_status_code", "Read request {:?} with value {:x?}", &req, &value);
                     Ok(value)
                 }
@@ -25,35 +54,42 @@ pub fn create_characteristic(state: &Arc<AppState>) -> Characteristic {
         }),
         notify: Some(CharacteristicNotify {
             notify: true,
+            indicate: true,
             method: CharacteristicNotifyMethod::Fun(Box::new(move |notifier| {
-                let notifier = Arc::new(Mutex::new(notifier));
-                let listeners = event_listeners_queue.clone();
+                let state = state_n.clone();
+                let updates = state.status_updates.subscribe();
+                let notify_task = notify_task.clone();
                 async move {
-                    let mut listeners = listeners.lock().await;
-                    if !listeners.is_empty() {
-                        for listener in listeners.iter() {
-                            debug!("Removing listener {}", listener);
-                            EVENT_EMITTER.lock().await.remove_listener(listener);
-                            debug!("Listener {} removed successfully", listener);
-                        }
+                    // Indications are confirmed by the client before the next one is sent
+                    debug!("Client subscribed to the status code {}", if notifier.confirming() { "indications" } else { "notifications" });
+                    let device = connections::subscriber(&state).await;
+                    match device {
+                        Some(device) => debug!("Notifying the status updates of device {}", device),
+                        None => warn!("Unable to tell the device that subscribed to the status code, notifying the updates of every device"),
                     }
-                    let notifier = notifier.clone();
-                    debug!("Initializing event {}", HTTP_STATUS_CODE_UPDATED_EVENT);
-                    let event = EVENT_EMITTER.lock().await.on(HTTP_STATUS_CODE_UPDATED_EVENT, move |value: Vec<u8>| {
-                        debug!("Event {} triggered", HTTP_STATUS_CODE_UPDATED_EVENT);
+                    let stopped = notifier.stopped();
+                    let notifier = Arc::new(Mutex::new(notifier));
+                    let forward = forward_updates(updates, device, move |value| {
                         let notifier = notifier.clone();
-                        debug!("Notifying with value {:x?}", &*value);
-                        futures::executor::block_on(async move {
-                            let mut notifier = notifier.lock().await;
-                            if let Err(err) = notifier.notify(value).await {
-                                warn!("Notification error: {}", &err);
-                                return;
+                        async move { notifier.lock().await.notify(value).await }
+                    });
+
+                    // Bind the forwarding to the notification session, so that it stops as soon
+                    // as the client unsubscribes or disconnects
+                    let task = tokio::spawn(async move {
+                        if cancel_on_disconnect {
+                            tokio::select! {
+                                _ = forward => {}
+                                _ = stopped => debug!("Status code notification session stopped"),
                             }
-                            debug!("Notification sent");
-                        });
+                        } else {
+                            forward.await;
+                        }
                     });
-                    *listeners = vec![event.clone()];
-                    debug!("Event {} with id {} intialized successfully", HTTP_STATUS_CODE_UPDATED_EVENT, event);       
+                    if let Some(previous) = notify_task.lock().await.replace(task) {
+                        previous.abort();
+                    }
+                    debug!("Status code notifications started");
                 }
                 .boxed()
             })),
@@ -61,4 +97,92 @@ pub fn create_characteristic(state: &Arc<AppState>) -> Characteristic {
         }),
         ..Default::default()
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app_state::STATUS_UPDATES_CAPACITY;
+    use std::time::Duration;
+
+    const DEVICE: Address = Address([0, 0, 0, 0, 0, 1]);
+
+    #[tokio::test]
+    async fn rapid_updates_are_all_forwarded() {
+        let (updates, receiver) = broadcast::channel(STATUS_UPDATES_CAPACITY);
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let sink = received.clone();
+        let forwarder = tokio::spawn(forward_updates(receiver, None, move |value| {
+            let sink = sink.clone();
+            async move {
+                sink.lock().await.push(value);
+                Ok(())
+            }
+        }));
+
+        for code in 0..1000u16 {
+            updates.send(StatusUpdate { device: DEVICE, value: code.to_le_bytes().to_vec() }).unwrap();
+            tokio::task::yield_now().await;
+        }
+        drop(updates);
+        tokio::time::timeout(Duration::from_secs(5), forwarder).await.unwrap().unwrap();
+
+        let received = received.lock().await;
+        let expected: Vec<Vec<u8>> = (0..1000u16).map(|code| code.to_le_bytes().to_vec()).collect();
+        assert_eq!(*received, expected);
+    }
+
+    #[tokio::test]
+    async fn failed_notification_drops_the_subscription() {
+        let (updates, receiver) = broadcast::channel(STATUS_UPDATES_CAPACITY);
+        // The client went away, so every notification fails
+        let forwarder = tokio::spawn(forward_updates(receiver, None, |_| async {
+            Err(std::io::Error::from(std::io::ErrorKind::NotConnected))
+        }));
+        assert_eq!(updates.receiver_count(), 1);
+
+        updates.send(StatusUpdate { device: DEVICE, value: vec![200, 0, 5] }).unwrap();
+        tokio::time::timeout(Duration::from_secs(5), forwarder).await.unwrap().unwrap();
+        assert_eq!(updates.receiver_count(), 0);
+    }
+
+    /// Forwards the updates sent by `send` for the subscriber `device`, returning the values notified.
+    async fn forwarded(device: Option<Address>, send: impl FnOnce(&broadcast::Sender<StatusUpdate>)) -> Vec<Vec<u8>> {
+        let (updates, receiver) = broadcast::channel(STATUS_UPDATES_CAPACITY);
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let sink = received.clone();
+        let forwarder = tokio::spawn(forward_updates(receiver, device, move |value| {
+            let sink = sink.clone();
+            async move {
+                sink.lock().await.push(value);
+                Ok(())
+            }
+        }));
+        send(&updates);
+        drop(updates);
+        tokio::time::timeout(Duration::from_secs(5), forwarder).await.unwrap().unwrap();
+        let received = received.lock().await.clone();
+        received
+    }
+
+    #[tokio::test]
+    async fn only_the_updates_of_the_subscriber_are_forwarded() {
+        let other = Address([0, 0, 0, 0, 0, 2]);
+        let send = |updates: &broadcast::Sender<StatusUpdate>| {
+            updates.send(StatusUpdate { device: DEVICE, value: vec![200, 0, 5] }).unwrap();
+            updates.send(StatusUpdate { device: other, value: vec![0x94, 0x01, 5] }).unwrap();
+            updates.send(StatusUpdate { device: DEVICE, value: vec![201, 0, 5] }).unwrap();
+        };
+        assert_eq!(forwarded(Some(DEVICE), send).await, [vec![200, 0, 5], vec![201, 0, 5]]);
+        assert_eq!(forwarded(Some(other), send).await, [vec![0x94, 0x01, 5]]);
+    }
+
+    #[tokio::test]
+    async fn unknown_subscriber_gets_every_update() {
+        let send = |updates: &broadcast::Sender<StatusUpdate>| {
+            updates.send(StatusUpdate { device: DEVICE, value: vec![200, 0, 5] }).unwrap();
+            updates.send(StatusUpdate { device: Address([0, 0, 0, 0, 0, 2]), value: vec![204, 0, 5] }).unwrap();
+        };
+        assert_eq!(forwarded(None, send).await, [vec![200, 0, 5], vec![204, 0, 5]]);
+    }
+}
@@ -1,11 +1,12 @@
-use crate::AppState;
+use crate::{bluetooth::access, config::WriteSecurity, http, AppState, Config};
 use bluer::gatt::local::{Characteristic, CharacteristicRead, CharacteristicWrite, CharacteristicWriteMethod};
 use futures::FutureExt;
 use std::sync::Arc;
 use tracing::debug;
 use crate::constants::HTTP_URI_UUID;
 
-pub fn create_characteristic(state: &Arc<AppState>) -> Characteristic {
+pub fn create_characteristic(state: &Arc<AppState>, config: &Config) -> Characteristic {
+    let allowlist = access::DeviceAllowlist::new(config);
     let state_r = state.clone();
     let state_w = state.clone();
     Characteristic {
@@ -13,9 +14,11 @@ pub fn create_characteristic(state: &Arc<AppState>) -> Characteristic {
         read: Some(CharacteristicRead {
             read: true,
             fun: Box::new(move |req| {
-                let value = state_r.http_uri.clone();
+                let state = state_r.clone();
                 async move {
-                    let value = value.lock().await.clone();
+                    let value = state.session(req.device_address).await.http_uri.read().await.clone();
+                    // Never echo back the credentials embedded in the URI
+                    let value = http::uri::sanitized(value);
                     debug!(target: "http_uri", "Read request {:?} with value {:x?}", &req, &value);
                     Ok(value)
                 }
@@ -25,12 +28,18 @@ pub fn create_characteristic(state: &Arc<AppState>) -> Characteristic {
         }),
         write: Some(CharacteristicWrite {
             write: true,
-            write_without_response: true,
+            write_without_response: !config.require_write_response,
+            encrypt_write: config.write_security == WriteSecurity::Encrypted,
+            encrypt_authenticated_write: config.write_security == WriteSecurity::Authenticated,
             method: CharacteristicWriteMethod::Fun(Box::new(move |new_value, req| {
-                let value = state_w.http_uri.clone();
+                let state = state_w.clone();
+                let allowlist = allowlist.clone();
                 async move {
-                    debug!(target: "http_uri", "Write request {:?} with value {:x?}", &req, &new_value);
-                    let mut value = value.lock().await;
+                    allowlist.check(req.device_address)?;
+                    // Nor log them
+                    debug!(target: "http_uri", "Write request {:?} with value {:x?}", &req, http::uri::sanitized(new_value.clone()));
+                    let session = state.session(req.device_address).await;
+                    let mut value = session.http_uri.write().await;
                     *value = new_value;
                     Ok(())
                 }
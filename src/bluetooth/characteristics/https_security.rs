@@ -12,9 +12,9 @@ pub fn create_characteristic(state: &Arc<AppState>) -> Characteristic {
         read: Some(CharacteristicRead {
             read: true,
             fun: Box::new(move |req| {
-                let value = state_r.https_security.clone();
+                let state = state_r.clone();
                 async move {
-                    let value = value.lock().await.clone();
+                    let value = state.session(req.device_address).await.https_security.read().await.clone();
                     debug!(target: "https_security", "Read request {:?} with value {:x?}", &req, &value);
                     Ok(value)
                 }
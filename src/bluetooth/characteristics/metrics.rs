@@ -0,0 +1,59 @@
+use crate::{AppState, Config};
+use bluer::gatt::local::{Characteristic, CharacteristicRead, CharacteristicNotify, CharacteristicNotifyMethod};
+use futures::FutureExt;
+use std::sync::Arc;
+use tracing::{debug, warn};
+use crate::constants::METRICS_UUID;
+
+pub fn create_characteristic(state: &Arc<AppState>, config: &Config) -> Characteristic {
+    let state_r = state.clone();
+    let state_n = state.clone();
+    let interval = config.metrics_interval_duration();
+    Characteristic {
+        uuid: *METRICS_UUID,
+        read: Some(CharacteristicRead {
+            read: true,
+            fun: Box::new(move |req| {
+                let value = state_r.metrics.encode();
+                async move {
+                    debug!(target: "metrics", "Read request {:?} with value {:x?}", &req, &value);
+                    Ok(value)
+                }
+                .boxed()
+            }),
+            ..Default::default()
+        }),
+        notify: Some(CharacteristicNotify {
+            notify: true,
+            method: CharacteristicNotifyMethod::Fun(Box::new(move |mut notifier| {
+                let state = state_n.clone();
+                async move {
+                    tokio::spawn(async move {
+                        let mut ticker = tokio::time::interval(interval);
+                        let mut last_value = Vec::new();
+                        loop {
+                            tokio::select! {
+                                _ = ticker.tick() => {}
+                                _ = notifier.stopped() => break,
+                            }
+                            let value = state.metrics.encode();
+                            if value == last_value {
+                                continue;
+                            }
+                            debug!(target: "metrics", "Notifying with value {:x?}", &value);
+                            if let Err(err) = notifier.notify(value.clone()).await {
+                                warn!(target: "metrics", "Notification error: {}", &err);
+                                break;
+                            }
+                            last_value = value;
+                        }
+                        debug!(target: "metrics", "Notification session stopped");
+                    });
+                }
+                .boxed()
+            })),
+            ..Default::default()
+        }),
+        ..Default::default()
+    }
+}
\ No newline at end of file
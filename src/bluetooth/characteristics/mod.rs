@@ -1,4 +1,8 @@
+mod adapter_info;
+mod basic_auth;
+mod diagnostics;
 mod headers_body_chunk_idx;
+mod headers_changed;
 mod headers_body_mtu_sizes;
 mod http_control_point;
 mod http_entity_body;
@@ -6,12 +10,22 @@ mod http_headers;
 mod http_status_code;
 mod http_uri;
 mod https_security;
+mod metrics;
+mod negotiated_mtu;
+mod request_timing;
 
+pub use adapter_info::create_characteristic as create_adapter_info;
+pub use basic_auth::create_characteristic as create_basic_auth;
+pub use diagnostics::create_characteristic as create_diagnostics;
 pub use headers_body_chunk_idx::create_characteristic as create_headers_body_chunk_idx;
 pub use headers_body_mtu_sizes::create_characteristic as create_headers_body_mtu_sizes;
+pub use headers_changed::create_characteristic as create_headers_changed;
 pub use http_control_point::create_characteristic as create_http_control_point;
 pub use http_entity_body::create_characteristic as create_http_entity_body;
 pub use http_headers::create_characteristic as create_http_headers;
 pub use http_status_code::create_characteristic as create_http_status_code;
 pub use http_uri::create_characteristic as create_http_uri;
-pub use https_security::create_characteristic as create_https_security;
\ No newline at end of file
+pub use https_security::create_characteristic as create_https_security;
+pub use metrics::create_characteristic as create_metrics;
+pub use negotiated_mtu::create_characteristic as create_negotiated_mtu;
+pub use request_timing::create_characteristic as create_request_timing;
\ No newline at end of file
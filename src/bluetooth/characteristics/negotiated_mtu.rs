@@ -0,0 +1,23 @@
+use bluer::gatt::local::{Characteristic, CharacteristicRead};
+use futures::FutureExt;
+use tracing::debug;
+use crate::constants::NEGOTIATED_MTU_UUID;
+
+pub fn create_characteristic() -> Characteristic {
+    Characteristic {
+        uuid: *NEGOTIATED_MTU_UUID,
+        read: Some(CharacteristicRead {
+            read: true,
+            fun: Box::new(move |req| {
+                async move {
+                    let value = req.mtu.to_le_bytes().to_vec();
+                    debug!(target: "negotiated_mtu", "Read request {:?} with value {:x?}", &req, &value);
+                    Ok(value)
+                }
+                .boxed()
+            }),
+            ..Default::default()
+        }),
+        ..Default::default()
+    }
+}
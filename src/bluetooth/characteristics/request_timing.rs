@@ -0,0 +1,27 @@
+use crate::AppState;
+use bluer::gatt::local::{Characteristic, CharacteristicRead};
+use futures::FutureExt;
+use std::sync::Arc;
+use tracing::debug;
+use crate::constants::REQUEST_TIMING_UUID;
+
+pub fn create_characteristic(state: &Arc<AppState>) -> Characteristic {
+    let state_r = state.clone();
+    Characteristic {
+        uuid: *REQUEST_TIMING_UUID,
+        read: Some(CharacteristicRead {
+            read: true,
+            fun: Box::new(move |req| {
+                let state = state_r.clone();
+                async move {
+                    let value = state.session(req.device_address).await.request_timing.read().await.clone();
+                    debug!(target: "request_timing", "Read request {:?} with value {:x?}", &req, &value);
+                    Ok(value)
+                }
+                .boxed()
+            }),
+            ..Default::default()
+        }),
+        ..Default::default()
+    }
+}
@@ -0,0 +1,47 @@
+use crate::AppState;
+use bluer::{Adapter, Address};
+use std::{sync::Arc, time::Duration};
+use tracing::debug;
+
+pub async fn is_connected(adapter: &Adapter, address: Address) -> bool {
+    match adapter.device(address) {
+        Ok(device) => device.is_connected().await.unwrap_or(false),
+        Err(_) => false,
+    }
+}
+
+/// The device that subscribed to a notification. BlueZ doesn't tell which device subscribed, so
+/// it's only known when a single device is connected: `None` otherwise, or without an adapter.
+pub async fn subscriber(state: &AppState) -> Option<Address> {
+    let adapter = state.adapter.get()?;
+    let mut connected = Vec::new();
+    for device in adapter.device_addresses().await.ok()? {
+        if is_connected(adapter, device).await {
+            connected.push(device);
+        }
+    }
+    match connected[..] {
+        [device] => Some(device),
+        _ => None,
+    }
+}
+
+/// Drops the per-device state of the devices that are no longer connected.
+pub async fn watch_connections(adapter: Adapter, state: Arc<AppState>) {
+    let mut ticker = tokio::time::interval(Duration::from_secs(1));
+    loop {
+        ticker.tick().await;
+
+        let mut devices: Vec<Address> = state.transferred_bytes.lock().await.keys().copied().collect();
+        devices.extend(state.request_counts.lock().await.keys().copied());
+        devices.sort();
+        devices.dedup();
+        for device in devices {
+            if !is_connected(&adapter, device).await {
+                debug!("Device {} disconnected, resetting its transferred bytes and request count", device);
+                state.transferred_bytes.lock().await.remove(&device);
+                state.request_counts.lock().await.remove(&device);
+            }
+        }
+    }
+}
@@ -1,6 +1,10 @@
+pub mod access;
 pub mod advertisement;
 pub mod application;
 pub mod characteristics;
+pub mod connections;
+pub mod retention;
+pub mod uuids;
 
 use crate::{AppState, Config, Result};
 use bluer::{
@@ -10,6 +14,7 @@ use bluer::{
 };
 use std::sync::Arc;
 use tracing::info;
+use uuids::Uuids;
 
 pub async fn setup_bluetooth() -> Result<Session> {
     let session = Session::new().await?;
@@ -25,8 +30,14 @@ pub async fn setup_bluetooth() -> Result<Session> {
     Ok(session)
 }
 
-pub async fn start_advertising(adapter: &Adapter, config: &Config) -> Result<AdvertisementHandle> {
-    let handle = advertisement::create_advertisement(adapter, config).await?;
+pub async fn adapter_info(adapter: &Adapter) -> Result<Vec<u8>> {
+    let mut info = adapter.address().await?.0.to_vec();
+    info.extend_from_slice(adapter.name().as_bytes());
+    Ok(info)
+}
+
+pub async fn start_advertising(adapter: &Adapter, config: &Config, uuids: &Uuids) -> Result<AdvertisementHandle> {
+    let handle = advertisement::create_advertisement(adapter, config, uuids).await?;
     info!("Started advertising");
     Ok(handle)
 }
@@ -35,8 +46,9 @@ pub async fn serve_gatt_application(
     adapter: &Adapter,
     state: &Arc<AppState>,
     config: &Config,
+    uuids: &Uuids,
 ) -> Result<bluer::gatt::local::ApplicationHandle> {
-    let app = application::create_application(state, config);
+    let app = application::create_application(state, config, uuids);
     let handle = adapter.serve_gatt_application(app).await?;
     info!("GATT application is now being served");
     Ok(handle)
@@ -0,0 +1,35 @@
+use crate::AppState;
+use bluer::{Adapter, Address};
+use std::{collections::HashMap, sync::Arc, time::{Duration, Instant}};
+use tracing::{debug, info};
+use super::connections::is_connected;
+
+/// Evicts the session of each device once it has been disconnected for `retention`, which
+/// keeps its last response available in the meantime, so that it can resume reading the
+/// chunks once reconnected.
+pub async fn watch_sessions(adapter: Adapter, state: Arc<AppState>, retention: Duration) {
+    let mut disconnected_since: HashMap<Address, Instant> = HashMap::new();
+    let mut ticker = tokio::time::interval(Duration::from_secs(1));
+    loop {
+        ticker.tick().await;
+
+        let devices: Vec<Address> = state.sessions.lock().await.keys().copied().collect();
+        disconnected_since.retain(|device, _| devices.contains(device));
+        for device in devices {
+            if is_connected(&adapter, device).await {
+                disconnected_since.remove(&device);
+                continue;
+            }
+
+            let since = *disconnected_since.entry(device).or_insert_with(|| {
+                debug!("Device {} disconnected, retaining its session for {:?}", device, retention);
+                Instant::now()
+            });
+            if since.elapsed() >= retention {
+                info!("Evicting the session of device {} after {:?} of disconnection", device, retention);
+                state.sessions.lock().await.remove(&device);
+                disconnected_since.remove(&device);
+            }
+        }
+    }
+}
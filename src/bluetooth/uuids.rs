@@ -0,0 +1,154 @@
+use crate::constants::{
+    ADAPTER_INFO_UUID, HEADERS_CHANGED_UUID, HTTPS_SECURITY_UUID, HTTP_CONTROL_POINT_UUID, HTTP_ENTITY_BODY_UUID, HTTP_HEADERS_BODY_CHUNK_IDX_UUID,
+    HTTP_HEADERS_BODY_SIZES_UUID, HTTP_HEADERS_UUID, HTTP_STATUS_CODE_UUID, HTTP_URI_UUID, METRICS_UUID, NEGOTIATED_MTU_UUID, REQUEST_TIMING_UUID, SERVICE_UUID, BASIC_AUTH_UUID, DIAGNOSTICS_UUID,
+};
+use bluer::UuidExt;
+use std::{collections::HashMap, str::FromStr};
+use uuid::Uuid;
+
+/// Names of the UUIDs that can be overridden, along with their default.
+fn defaults() -> [(&'static str, Uuid); 16] {
+    [
+        ("service", *SERVICE_UUID),
+        ("uri", *HTTP_URI_UUID),
+        ("headers", *HTTP_HEADERS_UUID),
+        ("status-code", *HTTP_STATUS_CODE_UUID),
+        ("entity-body", *HTTP_ENTITY_BODY_UUID),
+        ("control-point", *HTTP_CONTROL_POINT_UUID),
+        ("https-security", *HTTPS_SECURITY_UUID),
+        ("chunk-index", *HTTP_HEADERS_BODY_CHUNK_IDX_UUID),
+        ("sizes", *HTTP_HEADERS_BODY_SIZES_UUID),
+        ("adapter-info", *ADAPTER_INFO_UUID),
+        ("metrics", *METRICS_UUID),
+        ("headers-changed", *HEADERS_CHANGED_UUID),
+        ("request-timing", *REQUEST_TIMING_UUID),
+        ("negotiated-mtu", *NEGOTIATED_MTU_UUID),
+        ("basic-auth", *BASIC_AUTH_UUID),
+        ("diagnostics", *DIAGNOSTICS_UUID),
+    ]
+}
+
+/// Parses a full UUID, or the 4 hexadecimal digits of a 16-bit UUID.
+fn parse_uuid(value: &str) -> Result<Uuid, String> {
+    if value.len() == 4 {
+        return u16::from_str_radix(value, 16)
+            .map(Uuid::from_u16)
+            .map_err(|_| format!("invalid 16-bit UUID '{}'", value));
+    }
+    Uuid::parse_str(value).map_err(|err| format!("invalid UUID '{}': {}", value, err))
+}
+
+/// Override of the UUID of the service or of a characteristic, written as `name=uuid`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct UuidOverride {
+    pub name: String,
+    pub uuid: Uuid,
+}
+
+impl FromStr for UuidOverride {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let (name, uuid) = value.split_once('=').ok_or_else(|| "expected NAME=UUID".to_string())?;
+        if !defaults().iter().any(|(known, _)| *known == name) {
+            let names: Vec<&str> = defaults().iter().map(|(known, _)| *known).collect();
+            return Err(format!("unknown name '{}', expected one of: {}", name, names.join(", ")));
+        }
+        Ok(Self { name: name.to_string(), uuid: parse_uuid(uuid)? })
+    }
+}
+
+/// UUIDs of the GATT service and of its characteristics: the HPS ones, unless overridden.
+#[derive(Clone, Debug)]
+pub struct Uuids {
+    /// Effective UUID of each default one.
+    effective: HashMap<Uuid, Uuid>,
+}
+
+impl Uuids {
+    /// Applies the `overrides` in order, so the last one of each name wins.
+    pub fn new(overrides: &[UuidOverride]) -> Self {
+        let effective = defaults()
+            .into_iter()
+            .map(|(name, default)| {
+                let uuid = overrides
+                    .iter()
+                    .rev()
+                    .find(|o| o.name == name)
+                    .map(|o| o.uuid)
+                    .unwrap_or(default);
+                (default, uuid)
+            })
+            .collect();
+        Self { effective }
+    }
+
+    /// Fails when two names end up with the same UUID, e.g. an override taking the default UUID of
+    /// another characteristic, since BlueZ couldn't tell them apart.
+    pub fn check_unique(&self) -> Result<(), String> {
+        let mut seen: HashMap<Uuid, &str> = HashMap::new();
+        for (name, default) in defaults() {
+            let uuid = self.get(default);
+            if let Some(other) = seen.insert(uuid, name) {
+                return Err(format!("'{}' and '{}' have the same UUID {}", other, name, uuid));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn service(&self) -> Uuid {
+        self.get(*SERVICE_UUID)
+    }
+
+    /// Effective UUID of the one that defaults to `default`.
+    pub fn get(&self, default: Uuid) -> Uuid {
+        self.effective.get(&default).copied().unwrap_or(default)
+    }
+
+    /// Default UUID of the `effective` one.
+    pub fn default_of(&self, effective: Uuid) -> Uuid {
+        self.effective
+            .iter()
+            .find(|(_, uuid)| **uuid == effective)
+            .map(|(default, _)| *default)
+            .unwrap_or(effective)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn overrides_are_parsed() {
+        let o: UuidOverride = "service=6e400001-b5a3-f393-e0a9-e50e24dcca9e".parse().unwrap();
+        assert_eq!(o.uuid, Uuid::parse_str("6e400001-b5a3-f393-e0a9-e50e24dcca9e").unwrap());
+        let o: UuidOverride = "uri=ffe1".parse().unwrap();
+        assert_eq!(o.uuid, Uuid::from_u16(0xffe1));
+
+        assert!("service".parse::<UuidOverride>().is_err());
+        assert!("unknown=ffe1".parse::<UuidOverride>().is_err());
+        assert!("uri=not-a-uuid".parse::<UuidOverride>().is_err());
+        assert!("uri=fzz1".parse::<UuidOverride>().is_err());
+    }
+
+    #[test]
+    fn defaults_unless_overridden() {
+        let uuids = Uuids::new(&["uri=ffe1".parse().unwrap(), "uri=ffe2".parse().unwrap()]);
+        assert_eq!(uuids.service(), *SERVICE_UUID);
+        assert_eq!(uuids.get(*HTTP_URI_UUID), Uuid::from_u16(0xffe2));
+        assert_eq!(uuids.default_of(Uuid::from_u16(0xffe2)), *HTTP_URI_UUID);
+        assert_eq!(uuids.get(*HTTP_HEADERS_UUID), *HTTP_HEADERS_UUID);
+    }
+
+    #[test]
+    fn duplicated_uuids_are_rejected() {
+        assert!(Uuids::new(&[]).check_unique().is_ok());
+        assert!(Uuids::new(&["uri=ffe1".parse().unwrap(), "headers=ffe2".parse().unwrap()]).check_unique().is_ok());
+
+        let err = Uuids::new(&["uri=ffe1".parse().unwrap(), "headers=ffe1".parse().unwrap()]).check_unique().unwrap_err();
+        assert!(err.contains("'uri' and 'headers'"));
+        let headers = format!("headers={}", *HTTP_URI_UUID);
+        assert!(Uuids::new(&[headers.parse().unwrap()]).check_unique().is_err());
+    }
+}
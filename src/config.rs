@@ -1,15 +1,202 @@
-use clap::Parser;
-use std::time::Duration;
+use crate::{bluetooth::uuids::UuidOverride, http::uri};
+use clap::{Parser, ValueEnum};
+use std::{net::SocketAddr, path::PathBuf, time::Duration};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum DuplicateHeaders {
+    /// Sends every duplicated header as is
+    Append,
+    /// Keeps the last value of singular headers and combines list-valued headers
+    Merge,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum RequestTarget {
+    /// Sends the path and query only (`GET /path?query`)
+    Origin,
+    /// Sends the full URL (`GET http://host/path?query`), as expected by proxies
+    Absolute,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum LogFormat {
+    /// Human readable lines
+    Text,
+    /// One JSON object per event, with its target, spans and fields
+    Json,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum LogLevel {
+    Trace,
+    Debug,
+    Info,
+    Warn,
+    Error,
+}
+
+/// Security BlueZ requires from the writes of the characteristics.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum WriteSecurity {
+    /// Any connected device can write
+    None,
+    /// Writes require an encrypted link, so the device must be paired
+    Encrypted,
+    /// Writes require an encrypted link with a device paired with MITM protection (e.g. a passkey)
+    Authenticated,
+}
 
 #[derive(Clone, Debug, Parser)]
 #[command(author, version, about, long_about = None)]
 pub struct Config {
     #[arg(short, long, default_value = "HPS", help = "Service name")]
     pub name: String,
+    #[arg(long, help = "Fails when the service name can't be advertised, instead of falling back to a default name")]
+    pub strict_name: bool,
+    #[arg(long, allow_hyphen_values = true, help = "Advertised TX power in dBm, between -127 and 20")]
+    pub tx_power: Option<i16>,
+    #[arg(long, help = "Advertised GAP appearance, e.g. 1344 (0x0540) for a generic sensor")]
+    pub appearance: Option<u16>,
+    #[arg(long, requires = "manufacturer_data", help = "Company identifier of the advertised manufacturer data")]
+    pub manufacturer_id: Option<u16>,
+    #[arg(long, requires = "manufacturer_id", help = "Advertised manufacturer data, as hexadecimal bytes")]
+    pub manufacturer_data: Option<String>,
+    #[arg(long, help = "Advertised data of the HTTP Proxy service, as hexadecimal bytes")]
+    pub service_data: Option<String>,
+    #[arg(long = "uuid", value_name = "NAME=UUID", help = "Overrides the UUID of the service or of a characteristic, e.g. service=6e400001-b5a3-f393-e0a9-e50e24dcca9e. Can be repeated")]
+    pub uuids: Vec<UuidOverride>,
+    #[arg(long, help = "Skips the Bluetooth setup and serves the dry-run protocol over TCP instead, for testing the HTTP path without an adapter")]
+    pub no_bluetooth: bool,
+    #[arg(long, default_value = "127.0.0.1:7878", help = "Address the dry-run protocol is served on with --no-bluetooth")]
+    pub dry_run_address: SocketAddr,
+    #[arg(long, value_enum, default_value_t = LogFormat::Text, help = "Format of the logs")]
+    pub log_format: LogFormat,
+    #[arg(long, value_enum, default_value_t = LogLevel::Info, help = "Default level of the logs, the targets set by RUST_LOG use its levels instead")]
+    pub log_level: LogLevel,
+    #[arg(long, value_delimiter = ',', default_value = "Authorization,Cookie,Set-Cookie,Proxy-Authorization", help = "Comma separated list of the headers whose values are logged as ***")]
+    pub redacted_headers: Vec<String>,
+    #[arg(long, default_value = "32", help = "Maximum number of bytes of each body logged")]
+    pub log_body_bytes: usize,
+    #[arg(long, help = "Logs the full headers and bodies, sensitive values included")]
+    pub log_unredacted: bool,
     #[arg(short, long, default_value = "60", help = "HTTP requests timeout in seconds")]
     pub timeout: u64,
+    #[arg(long, help = "Fails the request if the response headers aren't received within this many seconds")]
+    pub response_header_timeout: Option<u64>,
     #[arg(short, long, default_value = "0", help = "Overrides the MTU size in bytes")]
     pub mtu: usize,
+    #[arg(long, default_value_t = true, action = clap::ArgAction::Set, help = "Stops the status code notifications when the client unsubscribes or disconnects")]
+    pub cancel_notify_on_disconnect: bool,
+    #[arg(long, default_value = "1048576", help = "Maximum size in bytes of the request body sent upstream")]
+    pub max_request_size: usize,
+    #[arg(long, default_value = "4", help = "Maximum number of concurrent requests to the same host")]
+    pub max_concurrent_per_host: usize,
+    #[arg(long, help = "Maximum bytes (requests and responses) a connected device can transfer before its requests are rejected")]
+    pub connection_byte_budget: Option<u64>,
+    #[arg(long, help = "Spools request bodies larger than this many bytes to a temporary file")]
+    pub spool_threshold: Option<usize>,
+    #[arg(long, help = "Directory of the spooled request bodies [default: the system temporary directory]")]
+    pub spool_dir: Option<PathBuf>,
+    #[arg(long, help = "Appends the response trailers to the response headers, after an empty line")]
+    pub capture_trailers: bool,
+    #[arg(long, value_enum, default_value_t = DuplicateHeaders::Merge, help = "How duplicated request headers are handled")]
+    pub duplicate_headers: DuplicateHeaders,
+    #[arg(long, value_enum, default_value_t = RequestTarget::Origin, help = "Form of the request target sent to HTTP servers")]
+    pub request_target: RequestTarget,
+    #[arg(long, default_value = "300", help = "Lifetime in seconds of the methods allowed by a preflight request")]
+    pub preflight_ttl: u64,
+    #[arg(long, help = "Sets the Content-Type of POST and PUT requests from the URI file extension, when not provided")]
+    pub guess_content_type: bool,
+    #[arg(long, help = "URL receiving a JSON summary (method, URI and status) of every completed request")]
+    pub audit_endpoint: Option<String>,
+    #[arg(long, default_value = "64", help = "Maximum number of audit records waiting to be sent")]
+    pub audit_queue_size: usize,
+    #[arg(long, help = "Allows clients to disable the certificate verification of a single request through the X-HPS-Insecure-TLS header")]
+    pub allow_insecure_override: bool,
+    #[arg(long, help = "Compresses the response headers with a static dictionary of common headers")]
+    pub compress_headers: bool,
+    #[arg(long, help = "Appends the CRC32 of the response headers and body to the HTTP Headers Body MTU Sizes characteristic")]
+    pub checksums: bool,
+    #[arg(long, help = "Logs the SHA-256 fingerprint of the server certificate of HTTPS requests and appends it to the HTTPS Security characteristic")]
+    pub report_tls_info: bool,
+    #[arg(long, help = "Rejects URIs with embedded credentials (user:password@host) instead of applying them as Basic auth")]
+    pub reject_uri_credentials: bool,
+    #[arg(long, default_value_t = true, action = clap::ArgAction::Set, help = "Returns an empty HTTP Entity Body until the first request has completed")]
+    pub empty_body_before_request: bool,
+    #[arg(long, help = "Exposes the request metrics through a notify characteristic")]
+    pub metrics: bool,
+    #[arg(long, default_value = "5", help = "Metrics notification interval in seconds")]
+    pub metrics_interval: u64,
+    #[arg(long, help = "Keeps the last response for this many seconds after the requesting device disconnects, then clears it")]
+    pub response_retention: Option<u64>,
+    #[arg(long, default_value = "10", help = "Seconds to wait on shutdown for the requests in flight to end")]
+    pub shutdown_grace_secs: u64,
+    #[arg(long, value_delimiter = ',', default_value = "80,443", help = "Comma separated list of the ports requests are allowed to target")]
+    pub allowed_ports: Vec<u16>,
+    #[arg(long = "allow-host", value_name = "HOST", help = "Host requests are allowed to target, or *.example.com for its subdomains. Can be repeated, requests to other hosts are then rejected")]
+    pub allowed_hosts: Vec<String>,
+    #[arg(long = "deny-host", value_name = "HOST", help = "Host requests are not allowed to target, or *.example.com for its subdomains. Can be repeated, and takes precedence over --allow-host")]
+    pub denied_hosts: Vec<String>,
+    #[arg(long, help = "Rejects the responses whose headers or body length doesn't fit the sizes characteristic, instead of reporting u32::MAX")]
+    pub reject_oversized_responses: bool,
+    #[arg(long, help = "Adds the HTTP version of the response to the HTTP Status Code characteristic")]
+    pub report_http_version: bool,
+    #[arg(long, help = "Prepends the status line of the response, e.g. HTTP/1.1 404 Not Found, to the response headers")]
+    pub status_line: bool,
+    #[arg(long, help = "Resolves {{name}} placeholders of the requests with the values extracted from the previous responses")]
+    pub templating: bool,
+    #[arg(long, help = "Flags the reads past the last chunk of the headers or body in the HTTP Status Code data status")]
+    pub report_chunk_overrun: bool,
+    #[arg(long, help = "Exposes a notify characteristic telling whether the response headers changed since the previous response")]
+    pub headers_change_notify: bool,
+    #[arg(long, help = "Pushes the body of each response through the HTTP Entity Body notifications, when a client is subscribed")]
+    pub push_body: bool,
+    #[arg(long, help = "Maximum number of requests a device can issue while connected before its state is reset")]
+    pub max_requests_per_connection: Option<u32>,
+    #[arg(long, help = "Disconnects the devices reaching --max-requests-per-connection")]
+    pub disconnect_on_request_cap: bool,
+    #[arg(long, help = "Interval in seconds between the sweeps of the stale cache entries, disabled when not set")]
+    pub cache_gc_interval: Option<u64>,
+    #[arg(long, default_value = "3600", help = "Age in seconds after which unused cache entries are swept")]
+    pub cache_max_age: u64,
+    #[arg(long, help = "Directory of the on-disk cache of GET responses, disabled when not set")]
+    pub disk_cache_dir: Option<PathBuf>,
+    #[arg(long, default_value = "10485760", help = "Maximum size in bytes of the on-disk response cache")]
+    pub disk_cache_max_size: u64,
+    #[arg(long, help = "Disables write-without-response on the writable characteristics, so that every write is acknowledged")]
+    pub require_write_response: bool,
+    #[arg(long, value_enum, default_value_t = WriteSecurity::None, help = "Security required from the writes of the characteristics")]
+    pub write_security: WriteSecurity,
+    #[arg(long = "allow-device", value_name = "ADDRESS", help = "Address of a device allowed to write the characteristics, e.g. 00:11:22:33:44:55. Can be repeated, writes of other devices are then rejected")]
+    pub allowed_devices: Vec<bluer::Address>,
+    #[arg(long, default_value_t = true, action = clap::ArgAction::Set, help = "Decodes the gzip, deflate and brotli encoded response bodies")]
+    pub decompress: bool,
+    #[arg(long, default_value = "16777216", help = "Maximum size in bytes of a decoded response body, larger bodies are left encoded")]
+    pub max_response_size: usize,
+    #[arg(long, default_value_t = true, action = clap::ArgAction::Set, help = "Follows the redirects, otherwise the 3xx responses are reported as they are")]
+    pub follow_redirects: bool,
+    #[arg(long, default_value = "10", help = "Maximum number of redirects followed by a request")]
+    pub max_redirects: usize,
+    #[arg(long, default_value = "0", help = "Number of retries of the idempotent requests failing with a connection error or a 5xx status")]
+    pub retries: u32,
+    #[arg(long, default_value = "200", help = "Delay in milliseconds before the first retry, doubled at each following one")]
+    pub retry_backoff_ms: u64,
+    #[arg(long, default_value = concat!("HPS-BLE/", env!("CARGO_PKG_VERSION")), help = "User-Agent of the requests that don't set one")]
+    pub user_agent: String,
+    #[arg(long, help = "PEM file of a root certificate trusted by the HTTPS requests, in addition to the system ones. Can be repeated")]
+    pub ca_cert: Vec<PathBuf>,
+    #[arg(long, requires = "client_key", help = "PEM file of the certificate presented to the servers of the HTTPS requests")]
+    pub client_cert: Option<PathBuf>,
+    #[arg(long, requires = "client_cert", help = "PEM file of the PKCS#8 private key of --client-cert")]
+    pub client_key: Option<PathBuf>,
+    #[arg(long, help = "URL of the proxy every request is sent through (http://, https:// or socks5://), with optional user:password@ credentials")]
+    pub upstream_proxy: Option<String>,
+    #[cfg(feature = "failure-injection")]
+    #[arg(long, help = "Simulates the failure requested through the X-HPS-Inject-Failure header instead of contacting the server")]
+    pub inject_failures: bool,
+    #[cfg(feature = "danger-insecure-tls")]
+    #[arg(long, help = "Skips the certificate verification of every HTTPS request, for development servers only")]
+    pub danger_insecure_tls: bool,
 }
 
 impl Config {
@@ -17,11 +204,77 @@ impl Config {
         Duration::from_secs(self.timeout)
     }
 
+    pub fn retry_backoff_duration(&self) -> Duration {
+        Duration::from_millis(self.retry_backoff_ms)
+    }
+
+    pub fn response_header_timeout_duration(&self) -> Option<Duration> {
+        self.response_header_timeout.map(Duration::from_secs)
+    }
+
+    pub fn metrics_interval_duration(&self) -> Duration {
+        Duration::from_secs(self.metrics_interval.max(1))
+    }
+
+    pub fn response_retention_duration(&self) -> Option<Duration> {
+        self.response_retention.map(Duration::from_secs)
+    }
+
+    pub fn shutdown_grace_duration(&self) -> Duration {
+        Duration::from_secs(self.shutdown_grace_secs)
+    }
+
+    pub fn cache_gc_interval_duration(&self) -> Option<Duration> {
+        self.cache_gc_interval.map(|interval| Duration::from_secs(interval.max(1)))
+    }
+
+    pub fn cache_max_age_duration(&self) -> Duration {
+        Duration::from_secs(self.cache_max_age)
+    }
+
+    pub fn preflight_ttl_duration(&self) -> Duration {
+        Duration::from_secs(self.preflight_ttl)
+    }
+
+    pub fn spool_dir(&self) -> PathBuf {
+        self.spool_dir.clone().unwrap_or_else(std::env::temp_dir)
+    }
+
+    /// Copy of the configuration that can be logged, with the credentials of the upstream
+    /// proxy masked.
+    pub fn redacted(&self) -> Self {
+        let mut config = self.clone();
+        if let Some(proxy) = config.upstream_proxy.as_mut() {
+            if let (mut sanitized, Some(_)) = uri::split_credentials(proxy) {
+                let authority_start = sanitized.find("://").map(|i| i + 3).unwrap_or(0);
+                sanitized.insert_str(authority_start, "***@");
+                *proxy = sanitized;
+            }
+        }
+        config
+    }
+
     pub fn effective_mtu(&self, established_mtu: usize) -> usize {
         if self.mtu > 0 && self.mtu < established_mtu {
             self.mtu
         } else {
-            established_mtu - crate::constants::MTU_OVERHEAD
+            // Never 0, so that the chunking math holds even on a bogus MTU
+            established_mtu.saturating_sub(crate::constants::MTU_OVERHEAD).max(1)
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn redacted_proxy_credentials() {
+        let config = Config::parse_from(["hps-ble", "--upstream-proxy", "http://alice:s3cr@t@proxy.local:3128"]);
+        let logged = format!("{:?}", config.redacted());
+        assert!(!logged.contains("s3cr@t"));
+        assert!(!logged.contains("alice"));
+        assert!(logged.contains("http://***@proxy.local:3128"));
+        assert_eq!(config.upstream_proxy.as_deref(), Some("http://alice:s3cr@t@proxy.local:3128"));
+    }
+}
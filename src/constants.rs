@@ -1,12 +1,17 @@
 use bluer::UuidExt;
-use event_emitter_rs::EventEmitter;
 use once_cell::sync::Lazy;
-use tokio::sync::Mutex;
 
 pub const MTU_OVERHEAD: usize = 3;
-pub const HTTP_STATUS_CODE_UPDATED_EVENT: &str = "http_status_code_updated";
+/// Chunk sizes below this make large responses take a huge number of reads.
+pub const LOW_CHUNK_SIZE_THRESHOLD: usize = 64;
+pub const HPS_INSECURE_TLS_HEADER: &str = "X-HPS-Insecure-TLS";
+pub const HPS_DEADLINE_HEADER: &str = "X-HPS-Deadline";
+pub const HPS_EXTRACT_HEADER: &str = "X-HPS-Extract";
+/// Response header carrying the URL the response came from, when redirects were followed.
+pub const HPS_FINAL_URL_HEADER: &str = "x-hps-final-url";
+#[cfg(feature = "failure-injection")]
+pub const HPS_INJECT_FAILURE_HEADER: &str = "X-HPS-Inject-Failure";
 
-pub static EVENT_EMITTER: Lazy<Mutex<EventEmitter>> = Lazy::new(|| Mutex::new(EventEmitter::new()));
 
 pub static SERVICE_UUID: Lazy<uuid::Uuid> = Lazy::new(|| uuid::Uuid::from_u16(0x1823));
 pub static HTTP_URI_UUID: Lazy<uuid::Uuid> = Lazy::new(|| uuid::Uuid::from_u16(0x2AB6));
@@ -17,3 +22,10 @@ pub static HTTP_CONTROL_POINT_UUID: Lazy<uuid::Uuid> = Lazy::new(|| uuid::Uuid::
 pub static HTTPS_SECURITY_UUID: Lazy<uuid::Uuid> = Lazy::new(|| uuid::Uuid::from_u16(0x2ABB));
 pub static HTTP_HEADERS_BODY_CHUNK_IDX_UUID: Lazy<uuid::Uuid> = Lazy::new(|| uuid::Uuid::from_u16(0x2A9A));
 pub static HTTP_HEADERS_BODY_SIZES_UUID: Lazy<uuid::Uuid> = Lazy::new(|| uuid::Uuid::from_u16(0x2AC0));
+pub static ADAPTER_INFO_UUID: Lazy<uuid::Uuid> = Lazy::new(|| uuid::Uuid::from_u128(0x48505342_0001_4000_8000_00000000b1e5));
+pub static METRICS_UUID: Lazy<uuid::Uuid> = Lazy::new(|| uuid::Uuid::from_u128(0x48505342_0002_4000_8000_00000000b1e5));
+pub static HEADERS_CHANGED_UUID: Lazy<uuid::Uuid> = Lazy::new(|| uuid::Uuid::from_u128(0x48505342_0003_4000_8000_00000000b1e5));
+pub static REQUEST_TIMING_UUID: Lazy<uuid::Uuid> = Lazy::new(|| uuid::Uuid::from_u128(0x48505342_0004_4000_8000_00000000b1e5));
+pub static NEGOTIATED_MTU_UUID: Lazy<uuid::Uuid> = Lazy::new(|| uuid::Uuid::from_u128(0x48505342_0005_4000_8000_00000000b1e5));
+pub static BASIC_AUTH_UUID: Lazy<uuid::Uuid> = Lazy::new(|| uuid::Uuid::from_u128(0x48505342_0006_4000_8000_00000000b1e5));
+pub static DIAGNOSTICS_UUID: Lazy<uuid::Uuid> = Lazy::new(|| uuid::Uuid::from_u128(0x48505342_0007_4000_8000_00000000b1e5));
@@ -0,0 +1,144 @@
+use crate::{app_state::Session, http::{handler, spool}, AppState, Config, Result};
+use bluer::Address;
+use std::{net::SocketAddr, sync::Arc};
+use tokio::{io::{AsyncBufReadExt, AsyncWriteExt, BufReader}, net::{TcpListener, TcpStream}};
+use tracing::{debug, info, warn};
+
+/// ATT MTU of the simulated connections, the largest one allowed by BLE.
+const DRY_RUN_MTU: usize = 517;
+
+/// Serves the dry-run protocol, which mirrors the characteristics over TCP so that the HTTP path
+/// can be exercised without a Bluetooth adapter. Each connection acts as a device, whose session
+/// ends along with the connection.
+pub async fn serve(listener: TcpListener, state: Arc<AppState>, config: Config) {
+    loop {
+        let (stream, peer) = match listener.accept().await {
+            Ok(connection) => connection,
+            Err(err) => {
+                warn!("Unable to accept a dry-run connection: {}", err);
+                continue;
+            }
+        };
+        let device = device_address(peer);
+        info!("Dry-run client {} connected as device {}", peer, device);
+        let state = state.clone();
+        let config = config.clone();
+        tokio::spawn(async move {
+            if let Err(err) = handle_client(stream, device, &state, &config).await {
+                warn!("Dry-run connection of device {} failed: {}", device, err);
+            }
+            state.sessions.lock().await.remove(&device);
+            debug!("Dry-run client of device {} disconnected", device);
+        });
+    }
+}
+
+/// Address of the device simulated by the client connected from `peer`, made of its port.
+fn device_address(peer: SocketAddr) -> Address {
+    let [high, low] = peer.port().to_be_bytes();
+    Address::new([0, 0, 0, 0, high, low])
+}
+
+async fn handle_client(stream: TcpStream, device: Address, state: &Arc<AppState>, config: &Config) -> Result<()> {
+    let (reader, mut writer) = stream.into_split();
+    let mut lines = BufReader::new(reader).lines();
+    while let Some(line) = lines.next_line().await? {
+        let answer = run_command(state, config, device, line.trim_end_matches('\r')).await;
+        writer.write_all(&answer).await?;
+    }
+    Ok(())
+}
+
+/// Runs a command line, returning the answer to send back:
+///
+/// - `set-uri <uri>`, `add-header <name>: <value>`, `set-body <text>` and `clear` write the
+///   request, answering `ok`
+/// - `send <opcode> [priority]` writes the HTTP Control Point, answering like `status` once the
+///   request is handled
+/// - `status` answers `status <code> <data status>`, or `status none` before any request
+/// - `get-headers` and `get-body` answer `data <length>`, followed by the whole response headers
+///   or body and a line feed
+async fn run_command(state: &Arc<AppState>, config: &Config, device: Address, line: &str) -> Vec<u8> {
+    debug!("Dry-run command of device {}: {}", device, line);
+    let (command, argument) = line.split_once(' ').unwrap_or((line, ""));
+    let session = state.session(device).await;
+    match command {
+        "set-uri" => *session.http_uri.write().await = argument.as_bytes().to_vec(),
+        "add-header" => {
+            let mut headers = session.request_headers.write().await;
+            headers.extend_from_slice(argument.as_bytes());
+            headers.extend_from_slice(b"\r\n");
+        }
+        "set-body" => {
+            if let Err(err) = spool::write_body_chunk(&session, config, 0, argument.as_bytes().to_vec()).await {
+                return format!("error {}\n", err).into_bytes();
+            }
+        }
+        "clear" => {
+            for buffer in [&session.http_uri, &session.request_headers, &session.request_body] {
+                buffer.write().await.clear();
+            }
+            *session.request_spool.lock().await = None;
+        }
+        "send" => {
+            let value: std::result::Result<Vec<u8>, _> = argument.split_whitespace().map(str::parse::<u8>).collect();
+            let Ok(value) = value else {
+                return format!("error invalid opcode '{}'\n", argument).into_bytes();
+            };
+            // Failures are reported through the status, like for the HTTP Control Point
+            let _ = handler::handle_http_control_point(state, value, device, config, config.effective_mtu(DRY_RUN_MTU)).await;
+            // The request cap resets the session of the device
+            return status(&state.session(device).await).await;
+        }
+        "status" => return status(&session).await,
+        "get-headers" => return data(&session.response_headers.read().await.clone()),
+        "get-body" => return data(&session.response_body.read().await.clone()),
+        _ => return format!("error unknown command '{}'\n", command).into_bytes(),
+    }
+    b"ok\n".to_vec()
+}
+
+async fn status(session: &Session) -> Vec<u8> {
+    match session.http_status_code.read().await.as_slice() {
+        [low, high, data_status, ..] => format!("status {} {:#04x}\n", u16::from_le_bytes([*low, *high]), data_status).into_bytes(),
+        _ => b"status none\n".to_vec(),
+    }
+}
+
+fn data(value: &[u8]) -> Vec<u8> {
+    let mut answer = format!("data {}\n", value.len()).into_bytes();
+    answer.extend_from_slice(value);
+    answer.push(b'\n');
+    answer
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+    use tokio::{io::AsyncReadExt, net::TcpListener};
+
+    #[tokio::test]
+    async fn request_through_the_dry_run_protocol() {
+        let upstream = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = upstream.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            let (mut socket, _) = upstream.accept().await.unwrap();
+            let mut buf = [0u8; 1024];
+            let _ = socket.read(&mut buf).await.unwrap();
+            socket.write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 5\r\n\r\nhello").await.unwrap();
+        });
+
+        let config = Config::parse_from(["hps-ble", "--allowed-ports", &port.to_string()]);
+        let state = Arc::new(AppState::new());
+        state.ready.store(true, std::sync::atomic::Ordering::SeqCst);
+        let device = Address::new([0, 0, 0, 0, 0, 1]);
+
+        assert_eq!(run_command(&state, &config, device, "status").await, b"status none\n");
+        let uri = format!("set-uri 127.0.0.1:{}/greeting", port);
+        assert_eq!(run_command(&state, &config, device, &uri).await, b"ok\n");
+        assert_eq!(run_command(&state, &config, device, "send 1").await, b"status 200 0x05\n");
+        assert_eq!(run_command(&state, &config, device, "get-body").await, b"data 5\nhello\n");
+        assert!(run_command(&state, &config, device, "send x").await.starts_with(b"error"));
+    }
+}
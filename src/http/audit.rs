@@ -0,0 +1,40 @@
+use serde::Serialize;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tracing::{debug, warn};
+
+#[derive(Clone, Debug, Serialize)]
+pub struct AuditRecord {
+    pub method: String,
+    pub uri: String,
+    pub status: u16,
+}
+
+/// Spawns the worker posting the audit records to `endpoint`. Records are dropped when more
+/// than `queue_size` are waiting, so that audit bursts never build up.
+pub fn spawn_audit_worker(endpoint: String, queue_size: usize, timeout: Duration) -> mpsc::Sender<AuditRecord> {
+    let (tx, mut rx) = mpsc::channel::<AuditRecord>(queue_size.max(1));
+    tokio::spawn(async move {
+        let client = reqwest::Client::new();
+        while let Some(record) = rx.recv().await {
+            let res = client
+                .post(&endpoint)
+                .timeout(timeout)
+                .json(&record)
+                .send()
+                .await
+                .and_then(|res| res.error_for_status());
+            match res {
+                Ok(_) => debug!("Audit record {:?} sent", &record),
+                Err(err) => warn!("Unable to send audit record {:?}: {}", &record, err),
+            }
+        }
+    });
+    tx
+}
+
+pub fn audit(tx: &mpsc::Sender<AuditRecord>, record: AuditRecord) {
+    if let Err(err) = tx.try_send(record) {
+        warn!("Dropping audit record: {}", err);
+    }
+}
@@ -0,0 +1,158 @@
+use crate::{error::Error, AppState, Config, Result};
+use super::{handler::HttpStatusSentinel, hosts};
+use reqwest::{redirect::Policy, Certificate, ClientBuilder, Identity, Proxy, Url};
+use std::{fmt, path::{Path, PathBuf}, sync::Arc};
+use tracing::{debug, info, warn};
+
+/// Redirect stopped because its target isn't allowed, failing the request with the sentinel.
+#[derive(Debug)]
+pub struct RedirectDenied(pub HttpStatusSentinel);
+
+impl fmt::Display for RedirectDenied {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "redirect denied: {:?}", self.0)
+    }
+}
+
+impl std::error::Error for RedirectDenied {}
+
+/// Follows up to `--max-redirects` redirects, each of them checked against `--allowed-ports`,
+/// `--allow-host` and `--deny-host` like the URI of the request.
+fn redirect_policy(config: &Config) -> Policy {
+    if !config.follow_redirects {
+        return Policy::none();
+    }
+    let max_redirects = config.max_redirects;
+    let allowed_ports = config.allowed_ports.clone();
+    let allowed_hosts = config.allowed_hosts.clone();
+    let denied_hosts = config.denied_hosts.clone();
+    Policy::custom(move |attempt| {
+        if attempt.previous().len() > max_redirects {
+            return attempt.error(format!("too many redirects (over {})", max_redirects));
+        }
+        if let Some(port) = attempt.url().port_or_known_default().filter(|port| !allowed_ports.contains(port)) {
+            warn!("Redirect to port {} is not allowed", port);
+            return attempt.error(RedirectDenied(HttpStatusSentinel::PortNotAllowed));
+        }
+        let host = attempt.url().host_str().unwrap_or_default();
+        if !hosts::is_allowed(host, &allowed_hosts, &denied_hosts) {
+            warn!("Redirect to host '{}' is not allowed", host);
+            return attempt.error(RedirectDenied(HttpStatusSentinel::HostNotAllowed));
+        }
+        attempt.follow()
+    })
+}
+
+/// Loads the root certificates of `paths`, each a PEM file, failing on the first one that
+/// can't be read or parsed.
+pub fn load_root_certificates(paths: &[PathBuf]) -> Result<Vec<Certificate>> {
+    paths
+        .iter()
+        .map(|path| {
+            let pem = std::fs::read(path)
+                .map_err(|err| Error::Application(format!("Unable to read the CA certificate {:?}: {}", path, err)))?;
+            let certificate = Certificate::from_pem(&pem)
+                .map_err(|err| Error::Application(format!("Invalid CA certificate {:?}: {}", path, err)))?;
+            info!("Trusting the CA certificate {:?}", path);
+            Ok(certificate)
+        })
+        .collect()
+}
+
+/// Loads the client certificate and its private key, both PEM files.
+pub fn load_identity(cert: &Path, key: &Path) -> Result<Identity> {
+    let read = |path: &Path| {
+        std::fs::read(path).map_err(|err| Error::Application(format!("Unable to read {:?}: {}", path, err)))
+    };
+    let identity = Identity::from_pkcs8_pem(&read(cert)?, &read(key)?)
+        .map_err(|err| Error::Application(format!("Invalid client certificate {:?} or key {:?}: {}", cert, key, err)))?;
+    info!("Presenting the client certificate {:?} to the HTTPS servers", cert);
+    Ok(identity)
+}
+
+/// Parses the URL of the upstream proxy, whose credentials (if any) are sent as Basic auth.
+/// SOCKS proxies resolve the hosts of the requests through `socks5h://`.
+pub fn load_upstream_proxy(url: &str) -> Result<Proxy> {
+    let mut parsed = Url::parse(url).map_err(|err| Error::Application(format!("Invalid upstream proxy: {}", err)))?;
+    if !matches!(parsed.scheme(), "http" | "https" | "socks5" | "socks5h") {
+        return Err(Error::Application(format!("Unsupported upstream proxy scheme '{}'", parsed.scheme())));
+    }
+    let mut proxy = Proxy::all(url)?;
+    // Credentials without password are ignored by the proxy parsing
+    if !parsed.username().is_empty() && parsed.password().is_none() {
+        proxy = proxy.basic_auth(parsed.username(), "");
+    }
+
+    // Never log the credentials
+    let _ = parsed.set_username("");
+    let _ = parsed.set_password(None);
+    info!("Sending the requests through the upstream proxy {}", parsed);
+    Ok(proxy)
+}
+
+fn builder(state: &AppState, config: &Config, insecure: bool) -> ClientBuilder {
+    let mut builder = reqwest::Client::builder()
+        .danger_accept_invalid_certs(insecure)
+        .redirect(redirect_policy(config))
+        .tls_info(config.report_tls_info)
+        .user_agent(&config.user_agent);
+    if let Some(proxy) = state.upstream_proxy.get() {
+        builder = builder.proxy(proxy.clone());
+    }
+    for certificate in state.root_certificates.get().into_iter().flatten() {
+        builder = builder.add_root_certificate(certificate.clone());
+    }
+    builder
+}
+
+/// Returns the client for the requested protocol and certificate verification mode, building
+/// it on first use. Clients are reused so that their connection pools and TLS sessions are
+/// kept. Only the clients of HTTPS requests present the client certificate, if any.
+pub async fn get(state: &Arc<AppState>, config: &Config, secure: bool, insecure: bool) -> Result<reqwest::Client> {
+    cached(state, config, (secure, insecure, false)).await
+}
+
+async fn cached(state: &Arc<AppState>, config: &Config, key: (bool, bool, bool)) -> Result<reqwest::Client> {
+    let (secure, insecure, absolute) = key;
+    let mut clients = state.http_clients.lock().await;
+    if let Some(client) = clients.get(&key) {
+        return Ok(client.clone());
+    }
+    let mut builder = builder(state, config, insecure);
+    if let Some(identity) = state.client_identity.get().filter(|_| secure) {
+        builder = builder.identity(identity.clone());
+    }
+    if absolute {
+        builder = builder.proxy(reqwest::Proxy::custom(|url| Some(url.origin().ascii_serialization())));
+    }
+    let client = builder.build()?;
+    clients.insert(key, client.clone());
+    Ok(client)
+}
+
+/// Builds the clients of the plain HTTP and HTTPS requests verifying the certificates, so that
+/// an invalid TLS, redirect or proxy configuration fails at startup rather than on the first
+/// request. Per-request settings, like the timeout, are applied to each request.
+pub async fn build_shared(state: &Arc<AppState>, config: &Config) -> Result<()> {
+    for secure in [false, true] {
+        get(state, config, secure, false).await?;
+    }
+    debug!("Built the shared HTTP clients");
+    Ok(())
+}
+
+/// Returns the client sending the absolute-form request target to the server of each request,
+/// by treating it as an HTTP proxy, built on first use like the other clients. Only plain HTTP
+/// requests can use the absolute form, HTTPS requests to a proxy are tunneled. The upstream
+/// proxy, if any, takes precedence, and it receives the absolute form anyway.
+pub async fn absolute_form(state: &Arc<AppState>, config: &Config, insecure: bool) -> Result<reqwest::Client> {
+    cached(state, config, (false, insecure, true)).await
+}
+
+/// Drops the clients along with their pooled connections, the following requests build new
+/// clients with the same configuration. Requests in flight keep their connections.
+pub async fn flush(state: &Arc<AppState>) {
+    let mut clients = state.http_clients.lock().await;
+    debug!("Flushing {} HTTP clients", clients.len());
+    clients.clear();
+}
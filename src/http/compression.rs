@@ -0,0 +1,181 @@
+/// Static dictionary of common response headers, similar in spirit to the HPACK static table.
+/// Entries are referenced by their 1-based position, so the order must never change.
+const STATIC_TABLE: &[(&str, Option<&str>)] = &[
+    ("content-type", Some("application/json")),
+    ("content-type", Some("text/html; charset=utf-8")),
+    ("content-type", Some("text/plain; charset=utf-8")),
+    ("content-type", None),
+    ("content-length", None),
+    ("content-encoding", Some("gzip")),
+    ("content-encoding", None),
+    ("cache-control", Some("no-cache")),
+    ("cache-control", Some("no-store")),
+    ("cache-control", None),
+    ("connection", Some("keep-alive")),
+    ("connection", Some("close")),
+    ("date", None),
+    ("server", None),
+    ("etag", None),
+    ("last-modified", None),
+    ("expires", None),
+    ("vary", Some("Accept-Encoding")),
+    ("vary", None),
+    ("transfer-encoding", Some("chunked")),
+    ("access-control-allow-origin", Some("*")),
+    ("access-control-allow-origin", None),
+    ("set-cookie", None),
+    ("location", None),
+    ("x-content-type-options", Some("nosniff")),
+    ("strict-transport-security", None),
+    ("accept-ranges", Some("bytes")),
+    ("age", None),
+    ("pragma", Some("no-cache")),
+    ("x-frame-options", Some("DENY")),
+    ("x-frame-options", Some("SAMEORIGIN")),
+    ("content-language", None),
+    ("allow", None),
+    ("www-authenticate", None),
+    ("retry-after", None),
+    ("x-powered-by", None),
+];
+
+const INDEXED: u8 = 0x80;
+const INDEXED_NAME: u8 = 0x40;
+const LITERAL: u8 = 0x00;
+const INDEX_MASK: u8 = 0x3F;
+
+fn table_entry(kind: u8) -> Option<&'static (&'static str, Option<&'static str>)> {
+    let index = (kind & INDEX_MASK) as usize;
+    STATIC_TABLE.get(index.checked_sub(1)?)
+}
+
+fn write_varint(buffer: &mut Vec<u8>, mut value: usize) {
+    loop {
+        let byte = (value & 0x7F) as u8;
+        value >>= 7;
+        if value == 0 {
+            buffer.push(byte);
+            return;
+        }
+        buffer.push(byte | 0x80);
+    }
+}
+
+fn read_varint(buffer: &[u8], pos: &mut usize) -> Option<usize> {
+    let mut value = 0usize;
+    let mut shift = 0;
+    loop {
+        let byte = *buffer.get(*pos)?;
+        *pos += 1;
+        value |= ((byte & 0x7F) as usize).checked_shl(shift)?;
+        if byte & 0x80 == 0 {
+            return Some(value);
+        }
+        shift += 7;
+    }
+}
+
+fn write_string(buffer: &mut Vec<u8>, value: &str) {
+    write_varint(buffer, value.len());
+    buffer.extend_from_slice(value.as_bytes());
+}
+
+fn read_string(buffer: &[u8], pos: &mut usize) -> Option<String> {
+    let len = read_varint(buffer, pos)?;
+    let bytes = buffer.get(*pos..pos.checked_add(len)?)?;
+    *pos += len;
+    String::from_utf8(bytes.to_vec()).ok()
+}
+
+/// Compresses a `name: value\r\n` headers block. Lines that aren't headers, like the status
+/// line or the empty line delimiting the trailers, are encoded as a literal with an empty name
+/// and the whole line as value.
+pub fn compress_headers(headers_str: &str) -> Vec<u8> {
+    let mut buffer = Vec::new();
+    for line in headers_str.split_terminator("\r\n") {
+        let (name, value) = match line.split_once(": ") {
+            Some((name, value)) if !name.is_empty() => (name, value),
+            _ => {
+                buffer.push(LITERAL);
+                write_string(&mut buffer, "");
+                write_string(&mut buffer, line);
+                continue;
+            }
+        };
+
+        let indexed = STATIC_TABLE
+            .iter()
+            .position(|(n, v)| n.eq_ignore_ascii_case(name) && *v == Some(value));
+        if let Some(i) = indexed {
+            buffer.push(INDEXED | (i as u8 + 1));
+            continue;
+        }
+
+        let indexed_name = STATIC_TABLE
+            .iter()
+            .position(|(n, _)| n.eq_ignore_ascii_case(name));
+        match indexed_name {
+            Some(i) => buffer.push(INDEXED_NAME | (i as u8 + 1)),
+            None => {
+                buffer.push(LITERAL);
+                write_string(&mut buffer, name);
+            }
+        }
+        write_string(&mut buffer, value);
+    }
+    buffer
+}
+
+/// Restores the `name: value\r\n` headers block produced by `compress_headers`.
+pub fn decompress_headers(buffer: &[u8]) -> Option<String> {
+    let mut headers_str = String::new();
+    let mut pos = 0;
+    while pos < buffer.len() {
+        let kind = buffer[pos];
+        pos += 1;
+        let (name, value) = if kind & INDEXED != 0 {
+            let &(name, value) = table_entry(kind)?;
+            (name.to_string(), value?.to_string())
+        } else if kind & INDEXED_NAME != 0 {
+            let &(name, _) = table_entry(kind)?;
+            (name.to_string(), read_string(buffer, &mut pos)?)
+        } else {
+            (read_string(buffer, &mut pos)?, read_string(buffer, &mut pos)?)
+        };
+
+        if name.is_empty() {
+            headers_str.push_str(&value);
+            headers_str.push_str("\r\n");
+        } else {
+            headers_str.push_str(&format!("{}: {}\r\n", name, value));
+        }
+    }
+    Some(headers_str)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(headers: &str) {
+        assert_eq!(decompress_headers(&compress_headers(headers)).as_deref(), Some(headers));
+    }
+
+    #[test]
+    fn headers_round_trip() {
+        round_trip("");
+        round_trip("content-type: application/json\r\ncontent-length: 42\r\nx-custom: value\r\n");
+        // Repeated names, both indexed and literal
+        round_trip("set-cookie: a=1\r\nset-cookie: b=2\r\nx-tag: one\r\nx-tag: two\r\n");
+        // Lines without a name/value separator, like the status line and the trailers delimiter
+        round_trip("HTTP/1.1 200 OK\r\nvary: Accept-Encoding\r\n\r\nx-checksum: abc\r\n");
+        round_trip("x-no-space:value\r\n: empty name\r\n");
+    }
+
+    #[test]
+    fn common_headers_are_indexed() {
+        assert_eq!(compress_headers("content-type: application/json\r\n"), vec![INDEXED | 1]);
+        assert_eq!(compress_headers("date: today\r\n"), [&[INDEXED_NAME | 13, 5][..], b"today"].concat());
+        assert!(decompress_headers(&[INDEXED | 63]).is_none());
+    }
+}
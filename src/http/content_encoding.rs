@@ -0,0 +1,38 @@
+use crate::{error::Error, Result};
+use flate2::read::{DeflateDecoder, GzDecoder, ZlibDecoder};
+use std::io::Read;
+
+const BROTLI_BUFFER_SIZE: usize = 4096;
+
+/// Reads the decoded body, failing once it exceeds `limit` bytes.
+fn read_all(reader: impl Read, limit: usize) -> Result<Vec<u8>> {
+    let mut decoded = Vec::new();
+    reader.take((limit as u64).saturating_add(1)).read_to_end(&mut decoded)?;
+    if decoded.len() > limit {
+        return Err(Error::Application(format!("Decoded body exceeds {} bytes", limit)));
+    }
+    Ok(decoded)
+}
+
+/// Decodes a body of the `deflate` coding, which should be zlib wrapped, but is sent as raw
+/// deflate data by some servers.
+fn inflate(body: &[u8], limit: usize) -> Result<Vec<u8>> {
+    read_all(ZlibDecoder::new(body), limit).or_else(|_| read_all(DeflateDecoder::new(body), limit))
+}
+
+/// Decodes `body` according to the value of its `Content-Encoding` header, undoing the codings
+/// in the reverse order of their application. Decoding fails once a coding yields more than
+/// `limit` bytes.
+pub fn decode(content_encoding: &str, body: &[u8], limit: usize) -> Result<Vec<u8>> {
+    let mut body = body.to_vec();
+    for coding in content_encoding.split(',').map(str::trim).rev() {
+        body = match coding.to_ascii_lowercase().as_str() {
+            "" | "identity" => body,
+            "gzip" | "x-gzip" => read_all(GzDecoder::new(&body[..]), limit)?,
+            "deflate" => inflate(&body, limit)?,
+            "br" => read_all(brotli::Decompressor::new(&body[..], BROTLI_BUFFER_SIZE), limit)?,
+            coding => return Err(Error::Application(format!("Unsupported content coding '{}'", coding))),
+        };
+    }
+    Ok(body)
+}
@@ -0,0 +1,53 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Margin taken off the remaining time, since the client clock may run ahead of ours.
+const CLOCK_SKEW_MARGIN: Duration = Duration::from_millis(500);
+
+/// Parses a deadline expressed as milliseconds since the Unix epoch.
+pub fn parse(value: &str) -> Option<SystemTime> {
+    let millis = value.trim().parse::<u64>().ok()?;
+    UNIX_EPOCH.checked_add(Duration::from_millis(millis))
+}
+
+/// Time left before `deadline`, never longer than `max`.
+/// Returns `None` when the deadline has already passed (skew margin included).
+pub fn remaining(deadline: SystemTime, max: Duration) -> Option<Duration> {
+    let remaining = deadline.duration_since(SystemTime::now()).ok()?;
+    let remaining = remaining.checked_sub(CLOCK_SKEW_MARGIN).filter(|remaining| !remaining.is_zero())?;
+    Some(remaining.min(max))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn millis_since_epoch(time: SystemTime) -> String {
+        time.duration_since(UNIX_EPOCH).unwrap().as_millis().to_string()
+    }
+
+    #[test]
+    fn future_deadline() {
+        let deadline = parse(&millis_since_epoch(SystemTime::now() + Duration::from_secs(10))).unwrap();
+        let left = remaining(deadline, Duration::from_secs(30)).unwrap();
+        assert!(left <= Duration::from_secs(10) - CLOCK_SKEW_MARGIN);
+        assert!(left > Duration::from_secs(9));
+        // Never longer than the timeout
+        assert_eq!(remaining(deadline, Duration::from_secs(1)), Some(Duration::from_secs(1)));
+    }
+
+    #[test]
+    fn past_deadline() {
+        let deadline = parse(&millis_since_epoch(SystemTime::now() - Duration::from_secs(1))).unwrap();
+        assert_eq!(remaining(deadline, Duration::from_secs(30)), None);
+        // Within the skew margin
+        let deadline = parse(&millis_since_epoch(SystemTime::now() + CLOCK_SKEW_MARGIN / 2)).unwrap();
+        assert_eq!(remaining(deadline, Duration::from_secs(30)), None);
+    }
+
+    #[test]
+    fn invalid_deadline() {
+        assert_eq!(parse(" 1000 "), Some(UNIX_EPOCH + Duration::from_secs(1)));
+        assert_eq!(parse("soon"), None);
+        assert_eq!(parse("-1"), None);
+    }
+}
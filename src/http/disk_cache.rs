@@ -0,0 +1,290 @@
+use crate::Result;
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use reqwest::header::{HeaderMap, CACHE_CONTROL};
+use std::{
+    io::{Cursor, Read},
+    path::PathBuf,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tokio::sync::Mutex;
+use tracing::{debug, warn};
+
+const MAGIC: &[u8; 4] = b"HPSC";
+const EXTENSION: &str = "cache";
+
+/// Response served from the disk cache.
+pub struct CachedResponse {
+    pub status: u16,
+    pub headers: String,
+    pub body: Vec<u8>,
+}
+
+/// On-disk cache of GET responses, keyed by URI. Entries expire according to the
+/// `Cache-Control: max-age` of the response, and the least recently used ones are evicted
+/// when the cache grows over `max_size` bytes.
+pub struct DiskCache {
+    dir: PathBuf,
+    max_size: u64,
+    // Serializes the cache accesses, entries are also written atomically through a rename
+    lock: Mutex<()>,
+}
+
+/// Lifetime of a response according to its `Cache-Control` header, if it can be cached.
+pub fn ttl(headers: &HeaderMap) -> Option<Duration> {
+    let mut max_age = None;
+    for directive in headers
+        .get_all(CACHE_CONTROL)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+    {
+        let directive = directive.trim().to_ascii_lowercase();
+        match directive.split_once('=') {
+            Some(("max-age", seconds)) => max_age = seconds.trim_matches('"').parse::<u64>().ok(),
+            _ if matches!(directive.as_str(), "no-store" | "no-cache" | "private") => return None,
+            _ => {}
+        }
+    }
+    max_age.filter(|&max_age| max_age > 0).map(Duration::from_secs)
+}
+
+/// Whether the request headers ask to bypass the cache.
+pub fn bypass(request_headers: &[(String, String)]) -> bool {
+    request_headers.iter().any(|(name, value)| {
+        name.eq_ignore_ascii_case(CACHE_CONTROL.as_str())
+            && value.split(',').any(|directive| {
+                matches!(directive.trim().to_ascii_lowercase().as_str(), "no-cache" | "no-store")
+            })
+    })
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|now| now.as_secs())
+        .unwrap_or_default()
+}
+
+fn encode(uri: &str, expires: u64, response: &CachedResponse) -> Result<Vec<u8>> {
+    let mut entry = Vec::with_capacity(MAGIC.len() + 26 + uri.len() + response.headers.len() + response.body.len());
+    entry.extend_from_slice(MAGIC);
+    entry.write_u64::<LittleEndian>(expires)?;
+    entry.write_u16::<LittleEndian>(response.status)?;
+    for field in [uri.as_bytes(), response.headers.as_bytes(), &response.body] {
+        entry.write_u32::<LittleEndian>(field.len() as u32)?;
+        entry.extend_from_slice(field);
+    }
+    let crc = crc32fast::hash(&entry);
+    entry.write_u32::<LittleEndian>(crc)?;
+    Ok(entry)
+}
+
+/// Decodes an entry, returning its URI, expiration and response, or `None` when corrupted.
+fn decode(entry: &[u8]) -> Option<(String, u64, CachedResponse)> {
+    let (content, crc) = entry.split_at(entry.len().checked_sub(4)?);
+    if !content.starts_with(MAGIC) || crc32fast::hash(content) != Cursor::new(crc).read_u32::<LittleEndian>().ok()? {
+        return None;
+    }
+
+    let mut cursor = Cursor::new(&content[MAGIC.len()..]);
+    let expires = cursor.read_u64::<LittleEndian>().ok()?;
+    let status = cursor.read_u16::<LittleEndian>().ok()?;
+    let mut fields = Vec::with_capacity(3);
+    for _ in 0..3 {
+        let len = cursor.read_u32::<LittleEndian>().ok()? as usize;
+        let mut field = vec![0; len];
+        cursor.read_exact(&mut field).ok()?;
+        fields.push(field);
+    }
+    let body = fields.pop()?;
+    let headers = String::from_utf8(fields.pop()?).ok()?;
+    let uri = String::from_utf8(fields.pop()?).ok()?;
+    Some((uri, expires, CachedResponse { status, headers, body }))
+}
+
+impl DiskCache {
+    pub fn new(dir: PathBuf, max_size: u64) -> Self {
+        Self { dir, max_size, lock: Mutex::new(()) }
+    }
+
+    fn path(&self, uri: &str) -> PathBuf {
+        self.dir.join(format!("{:08x}{:08x}.{}", crc32fast::hash(uri.as_bytes()), uri.len(), EXTENSION))
+    }
+
+    /// Returns the cached response of `uri`, if any and not expired. Corrupted and expired
+    /// entries are removed.
+    pub async fn get(&self, uri: &str) -> Option<CachedResponse> {
+        let _guard = self.lock.lock().await;
+        let path = self.path(uri);
+        let entry = tokio::fs::read(&path).await.ok()?;
+        match decode(&entry) {
+            Some((cached_uri, expires, response)) if cached_uri == uri => {
+                if expires <= unix_now() {
+                    debug!("Disk cache entry of '{}' expired", uri);
+                    let _ = tokio::fs::remove_file(&path).await;
+                    return None;
+                }
+                // The modification time tracks the last use, for the LRU eviction. Tokio has no
+                // async equivalent of `set_modified`
+                let _ = tokio::task::spawn_blocking(move || {
+                    std::fs::File::options().append(true).open(&path)?.set_modified(SystemTime::now())
+                })
+                .await;
+                Some(response)
+            }
+            Some(_) => None,
+            None => {
+                warn!("Removing corrupted disk cache entry {:?}", &path);
+                let _ = tokio::fs::remove_file(&path).await;
+                None
+            }
+        }
+    }
+
+    /// Stores the response of `uri` for `ttl`, then evicts the least recently used entries
+    /// until the cache fits its maximum size.
+    pub async fn put(&self, uri: &str, ttl: Duration, response: &CachedResponse) -> Result<()> {
+        let entry = encode(uri, unix_now() + ttl.as_secs(), response)?;
+        if entry.len() as u64 > self.max_size {
+            debug!("Response of '{}' is larger than the disk cache, not caching it", uri);
+            return Ok(());
+        }
+
+        let _guard = self.lock.lock().await;
+        tokio::fs::create_dir_all(&self.dir).await?;
+        let path = self.path(uri);
+        let tmp_path = path.with_extension("tmp");
+        tokio::fs::write(&tmp_path, &entry).await?;
+        tokio::fs::rename(&tmp_path, &path).await?;
+        debug!("Cached {} bytes for '{}' in {:?}", entry.len(), uri, &path);
+        self.evict().await
+    }
+
+    /// Removes the entries that expired or weren't used for `max_age`, returning their number.
+    pub async fn collect(&self, max_age: Duration) -> Result<usize> {
+        let _guard = self.lock.lock().await;
+        let mut dir = match tokio::fs::read_dir(&self.dir).await {
+            Ok(dir) => dir,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(0),
+            Err(err) => return Err(err.into()),
+        };
+        let now = unix_now();
+        let mut removed = 0;
+        while let Some(entry) = dir.next_entry().await? {
+            let path = entry.path();
+            if !path.extension().is_some_and(|extension| extension == EXTENSION) {
+                continue;
+            }
+            let unused = entry
+                .metadata()
+                .await?
+                .modified()
+                .ok()
+                .and_then(|modified| modified.elapsed().ok())
+                .is_some_and(|unused| unused >= max_age);
+            let expired = match tokio::fs::read(&path).await {
+                Ok(content) => match decode(&content) {
+                    Some((_, expires, _)) => expires <= now,
+                    None => true,
+                },
+                Err(_) => false,
+            };
+            if unused || expired {
+                debug!("Collecting disk cache entry {:?}", &path);
+                tokio::fs::remove_file(&path).await?;
+                removed += 1;
+            }
+        }
+        Ok(removed)
+    }
+
+    async fn evict(&self) -> Result<()> {
+        let mut entries = Vec::new();
+        let mut dir = tokio::fs::read_dir(&self.dir).await?;
+        while let Some(entry) = dir.next_entry().await? {
+            let path = entry.path();
+            if path.extension().is_some_and(|extension| extension == EXTENSION) {
+                let metadata = entry.metadata().await?;
+                entries.push((metadata.modified().unwrap_or(UNIX_EPOCH), metadata.len(), path));
+            }
+        }
+
+        let mut size: u64 = entries.iter().map(|(_, len, _)| len).sum();
+        entries.sort_by_key(|(modified, _, _)| *modified);
+        for (_, len, path) in entries {
+            if size <= self.max_size {
+                break;
+            }
+            debug!("Evicting disk cache entry {:?}", &path);
+            tokio::fs::remove_file(&path).await?;
+            size -= len;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cache(name: &str, max_size: u64) -> DiskCache {
+        let dir = std::env::temp_dir().join(format!("hps-ble-cache-test-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        DiskCache::new(dir, max_size)
+    }
+
+    fn response(body: &[u8]) -> CachedResponse {
+        CachedResponse { status: 200, headers: "content-type: text/plain\r\n".to_string(), body: body.to_vec() }
+    }
+
+    #[tokio::test]
+    async fn hit() {
+        let cache = cache("hit", 1024);
+        assert!(cache.get("http://example.com/a").await.is_none());
+        cache.put("http://example.com/a", Duration::from_secs(60), &response(b"hello")).await.unwrap();
+
+        let cached = cache.get("http://example.com/a").await.unwrap();
+        assert_eq!(cached.status, 200);
+        assert_eq!(cached.headers, "content-type: text/plain\r\n");
+        assert_eq!(cached.body, b"hello");
+        assert!(cache.get("http://example.com/b").await.is_none());
+        std::fs::remove_dir_all(&cache.dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn least_recently_used_is_evicted() {
+        // Each entry takes about 100 bytes, so only two of them fit
+        let cache = cache("eviction", 250);
+        cache.put("http://example.com/1", Duration::from_secs(60), &response(&[1; 40])).await.unwrap();
+        cache.put("http://example.com/2", Duration::from_secs(60), &response(&[2; 40])).await.unwrap();
+        let past = SystemTime::now() - Duration::from_secs(60);
+        std::fs::File::options().append(true).open(cache.path("http://example.com/2")).unwrap().set_modified(past).unwrap();
+        std::fs::File::options().append(true).open(cache.path("http://example.com/1")).unwrap().set_modified(past - Duration::from_secs(60)).unwrap();
+        // Reading the first entry makes the second one the least recently used
+        assert!(cache.get("http://example.com/1").await.is_some());
+
+        cache.put("http://example.com/3", Duration::from_secs(60), &response(&[3; 40])).await.unwrap();
+        assert!(cache.get("http://example.com/1").await.is_some());
+        assert!(cache.get("http://example.com/2").await.is_none());
+        assert!(cache.get("http://example.com/3").await.is_some());
+        std::fs::remove_dir_all(&cache.dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn corrupted_entry_is_discarded() {
+        let cache = cache("corruption", 1024);
+        cache.put("http://example.com/a", Duration::from_secs(60), &response(b"hello")).await.unwrap();
+        let path = cache.path("http://example.com/a");
+        let mut entry = std::fs::read(&path).unwrap();
+        let last = entry.len() - 5;
+        entry[last] ^= 0xff;
+        std::fs::write(&path, entry).unwrap();
+
+        assert!(cache.get("http://example.com/a").await.is_none());
+        assert!(!path.exists());
+        // The cache recovers once the response is stored again
+        cache.put("http://example.com/a", Duration::from_secs(60), &response(b"hello")).await.unwrap();
+        assert_eq!(cache.get("http://example.com/a").await.unwrap().body, b"hello");
+        std::fs::remove_dir_all(&cache.dir).unwrap();
+    }
+}
@@ -0,0 +1,38 @@
+use crate::AppState;
+use std::{sync::Arc, time::Duration};
+use tracing::{debug, warn};
+
+/// Periodically drops the per-URI and per-host state that expired or wasn't used for
+/// `max_age`. Each map is locked only for the time needed to sweep it.
+pub async fn collect_garbage(state: Arc<AppState>, interval: Duration, max_age: Duration) {
+    let mut ticker = tokio::time::interval(interval);
+    loop {
+        ticker.tick().await;
+
+        let mut preflight_cache = state.preflight_cache.lock().await;
+        let before = preflight_cache.len();
+        preflight_cache.retain(|_, entry| !entry.is_expired() && entry.created.elapsed() < max_age);
+        let preflights = before - preflight_cache.len();
+        drop(preflight_cache);
+
+        // Queues without running nor waiting requests are only referenced by the map
+        let mut host_queues = state.host_queues.lock().await;
+        let before = host_queues.len();
+        host_queues.retain(|_, queue| Arc::strong_count(queue) > 1);
+        let queues = before - host_queues.len();
+        drop(host_queues);
+
+        let cached_responses = match state.disk_cache.get() {
+            Some(disk_cache) => disk_cache.collect(max_age).await.unwrap_or_else(|err| {
+                warn!("Unable to collect the disk cache: {}", err);
+                0
+            }),
+            None => 0,
+        };
+
+        debug!(
+            "Collected {} preflights, {} host queues and {} cached responses",
+            preflights, queues, cached_responses
+        );
+    }
+}
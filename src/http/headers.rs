@@ -0,0 +1,153 @@
+use base64::{engine::general_purpose::STANDARD, Engine};
+use reqwest::header::AUTHORIZATION;
+use zeroize::Zeroizing;
+
+pub type Headers = Vec<(String, String)>;
+
+/// Request headers that can't be repeated or combined into a list, the last value wins.
+pub const SINGULAR_HEADERS: &[&str] = &[
+    "authorization",
+    "content-length",
+    "content-type",
+    "date",
+    "expect",
+    "from",
+    "host",
+    "if-modified-since",
+    "if-unmodified-since",
+    "max-forwards",
+    "origin",
+    "proxy-authorization",
+    "range",
+    "referer",
+    "user-agent",
+];
+
+pub fn parse_headers(headers_str: &str) -> Headers {
+    headers_str
+        .split("\r\n")
+        .filter_map(|h| {
+            let i = h.find(':')?;
+            let (header_key, header_value) = h.split_at(i);
+            let header_key = header_key.trim();
+            let header_value = header_value[1..].trim(); // Skip the ':' and trim
+            Some((header_key.to_string(), header_value.to_string()))
+        })
+        .collect()
+}
+
+pub fn has_header(headers: &Headers, name: &str) -> bool {
+    headers.iter().any(|(k, _)| k.eq_ignore_ascii_case(name))
+}
+
+/// Removes every occurrence of the header `name`, returning the last value found.
+pub fn take_header(headers: &mut Headers, name: &str) -> Option<String> {
+    let mut value = None;
+    headers.retain(|(k, v)| {
+        if k.eq_ignore_ascii_case(name) {
+            value = Some(v.clone());
+            false
+        } else {
+            true
+        }
+    });
+    value
+}
+
+/// `Authorization` header of the Basic auth `credentials` (`username:password`, or a username
+/// alone), unless `headers` already have one. The credentials are zeroized once encoded.
+pub fn basic_authorization(headers: &Headers, credentials: Zeroizing<Vec<u8>>) -> Option<String> {
+    if credentials.is_empty() || has_header(headers, AUTHORIZATION.as_str()) {
+        return None;
+    }
+    let mut user_pass = Zeroizing::new(credentials.to_vec());
+    if !user_pass.contains(&b':') {
+        user_pass.push(b':');
+    }
+    Some(format!("Basic {}", *Zeroizing::new(STANDARD.encode(&*user_pass))))
+}
+
+/// Merges the duplicated headers following the HTTP semantics: singular headers keep the last
+/// value, list-valued headers are combined into a single comma separated value.
+pub fn merge_duplicates(headers: Headers) -> Headers {
+    let mut merged: Headers = Vec::with_capacity(headers.len());
+    for (key, value) in headers {
+        let singular = SINGULAR_HEADERS.iter().any(|h| h.eq_ignore_ascii_case(&key));
+        let separator = if key.eq_ignore_ascii_case("cookie") { "; " } else { ", " };
+        match merged.iter_mut().find(|(k, _)| k.eq_ignore_ascii_case(&key)) {
+            Some((_, existing)) if singular => *existing = value,
+            Some((_, existing)) => {
+                existing.push_str(separator);
+                existing.push_str(&value);
+            }
+            None => merged.push((key, value)),
+        }
+    }
+    merged
+}
+
+/// Validators identifying a response version, when the server provides them.
+const VALIDATOR_HEADERS: &[&str] = &["etag", "last-modified"];
+/// Headers changing on every response, regardless of the content.
+const VOLATILE_HEADERS: &[&str] = &["age", "date"];
+
+/// CRC32 of the response headers identifying the response content: the validators when
+/// provided, otherwise all the headers except the volatile ones.
+pub fn fingerprint(headers_str: &str) -> u32 {
+    let headers: Headers = parse_headers(headers_str)
+        .into_iter()
+        .map(|(name, value)| (name.to_ascii_lowercase(), value))
+        .collect();
+    let has_validators = headers.iter().any(|(name, _)| VALIDATOR_HEADERS.contains(&name.as_str()));
+
+    let mut hasher = crc32fast::Hasher::new();
+    for (name, value) in headers.iter().filter(|(name, _)| {
+        if has_validators {
+            VALIDATOR_HEADERS.contains(&name.as_str())
+        } else {
+            !VOLATILE_HEADERS.contains(&name.as_str())
+        }
+    }) {
+        hasher.update(name.as_bytes());
+        hasher.update(b":");
+        hasher.update(value.as_bytes());
+        hasher.update(b"\n");
+    }
+    hasher.finalize()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn credentials(value: &str) -> Zeroizing<Vec<u8>> {
+        Zeroizing::new(value.as_bytes().to_vec())
+    }
+
+    #[test]
+    fn basic_authorization_is_synthesized() {
+        let headers = parse_headers("Accept: */*");
+        assert_eq!(basic_authorization(&headers, credentials("user:pass")), Some("Basic dXNlcjpwYXNz".to_string()));
+        assert_eq!(basic_authorization(&headers, credentials("user")), Some("Basic dXNlcjo=".to_string()));
+        assert_eq!(basic_authorization(&headers, credentials("")), None);
+    }
+
+    #[test]
+    fn explicit_authorization_wins() {
+        let headers = parse_headers("authorization: Bearer token\r\nAccept: */*");
+        assert_eq!(basic_authorization(&headers, credentials("user:pass")), None);
+    }
+
+    #[test]
+    fn duplicates_are_merged() {
+        let headers = parse_headers("Content-Type: text/plain\r\nAccept: text/html\r\ncontent-type: application/json\r\naccept: */*\r\nCookie: a=1\r\nCookie: b=2");
+        assert_eq!(
+            merge_duplicates(headers),
+            [
+                ("Content-Type".to_string(), "application/json".to_string()),
+                ("Accept".to_string(), "text/html, */*".to_string()),
+                ("Cookie".to_string(), "a=1; b=2".to_string()),
+            ]
+        );
+    }
+}
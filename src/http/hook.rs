@@ -0,0 +1,58 @@
+use super::headers::Headers;
+use reqwest::{header::HeaderMap, Method, Url};
+use std::fmt;
+
+/// Request about to be sent, as seen by the hooks.
+pub struct RequestParts {
+    pub(crate) method: Method,
+    pub(crate) url: Url,
+    pub headers: Headers,
+    pub body: Vec<u8>,
+}
+
+impl RequestParts {
+    pub fn method(&self) -> &Method {
+        &self.method
+    }
+
+    pub fn url(&self) -> &Url {
+        &self.url
+    }
+}
+
+/// Response received from the server, before it's written to the characteristics.
+pub struct ResponseParts {
+    pub status: u16,
+    pub headers: HeaderMap,
+    pub body: Vec<u8>,
+}
+
+/// Reason given by a hook for rejecting a request or a response.
+#[derive(Clone, Debug)]
+pub struct HookError(pub String);
+
+impl fmt::Display for HookError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for HookError {}
+
+/// Middleware invoked around each proxied request, which can rewrite its headers and body (the
+/// method and URL are read-only) and the response. An error rejects the request with the
+/// sentinel 32 instead of sending it, or instead of reporting the response.
+pub trait ProxyHook: Send + Sync {
+    fn on_request(&self, _request: &mut RequestParts) -> Result<(), HookError> {
+        Ok(())
+    }
+
+    fn on_response(&self, _response: &mut ResponseParts) -> Result<(), HookError> {
+        Ok(())
+    }
+}
+
+/// Hook used when none is set, leaving the requests and responses untouched.
+pub struct NoopHook;
+
+impl ProxyHook for NoopHook {}
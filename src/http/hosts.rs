@@ -0,0 +1,56 @@
+/// Whether `host` matches `pattern`, either exactly or, for the `*.example.com` patterns, as a
+/// subdomain of `example.com` (but not `example.com` itself). Hosts are case insensitive.
+fn matches(host: &str, pattern: &str) -> bool {
+    let pattern = pattern.trim_end_matches('.');
+    match pattern.strip_prefix("*.") {
+        Some(domain) => host
+            .len()
+            .checked_sub(domain.len() + 1)
+            .is_some_and(|dot| host.as_bytes()[dot] == b'.' && host[dot + 1..].eq_ignore_ascii_case(domain)),
+        None => host.eq_ignore_ascii_case(pattern),
+    }
+}
+
+/// Whether requests can target `host`: it must not match any of the `denied` patterns and, when
+/// there are `allowed` ones, it must match one of them.
+pub fn is_allowed(host: &str, allowed: &[String], denied: &[String]) -> bool {
+    let host = host.trim_end_matches('.');
+    if denied.iter().any(|pattern| matches(host, pattern)) {
+        return false;
+    }
+    allowed.is_empty() || allowed.iter().any(|pattern| matches(host, pattern))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn patterns(patterns: &[&str]) -> Vec<String> {
+        patterns.iter().map(|pattern| pattern.to_string()).collect()
+    }
+
+    #[test]
+    fn allowed_host_passes() {
+        let allowed = patterns(&["example.com", "*.example.org"]);
+        assert!(is_allowed("example.com", &allowed, &[]));
+        assert!(is_allowed("EXAMPLE.com.", &allowed, &[]));
+        assert!(is_allowed("api.example.org", &allowed, &[]));
+        assert!(is_allowed("v1.api.example.org", &allowed, &[]));
+        assert!(is_allowed("anything.net", &[], &[]));
+    }
+
+    #[test]
+    fn denied_host_is_blocked() {
+        let allowed = patterns(&["example.com", "*.example.org"]);
+        assert!(!is_allowed("example.org", &allowed, &[]));
+        assert!(!is_allowed("badexample.org", &allowed, &[]));
+        assert!(!is_allowed("www.example.com", &allowed, &[]));
+
+        let denied = patterns(&["*.internal", "169.254.169.254"]);
+        assert!(!is_allowed("db.internal", &[], &denied));
+        assert!(!is_allowed("169.254.169.254", &[], &denied));
+        assert!(is_allowed("internal", &[], &denied));
+        // Denied patterns take precedence over the allowed ones
+        assert!(!is_allowed("db.internal", &patterns(&["*.internal"]), &patterns(&["db.internal"])));
+    }
+}
@@ -1 +1,22 @@
-pub mod handler;
\ No newline at end of file
+pub mod audit;
+pub mod client;
+pub mod compression;
+pub mod content_encoding;
+pub mod deadline;
+pub mod disk_cache;
+pub mod gc;
+pub mod handler;
+pub mod headers;
+pub mod hook;
+pub mod hosts;
+pub mod mime;
+pub mod preflight;
+pub mod queue;
+pub mod resolve;
+pub mod retry;
+pub mod spool;
+pub mod stream;
+pub mod template;
+pub mod tls;
+pub mod transport;
+pub mod uri;
\ No newline at end of file
@@ -0,0 +1,46 @@
+use reqwest::{header::HeaderMap, Method};
+use std::time::{Duration, Instant};
+
+const ALLOW_HEADERS: &[&str] = &["allow", "access-control-allow-methods"];
+const MAX_AGE_HEADER: &str = "access-control-max-age";
+
+pub struct PreflightEntry {
+    pub allowed: Vec<Method>,
+    pub created: Instant,
+    pub expires: Instant,
+}
+
+impl PreflightEntry {
+    /// Builds the entry from the `Allow`/`Access-Control-Allow-*` headers of an OPTIONS response.
+    /// `Access-Control-Max-Age` shortens the lifetime when lower than `ttl`.
+    pub fn from_headers(headers: &HeaderMap, ttl: Duration) -> Self {
+        let allowed = ALLOW_HEADERS
+            .iter()
+            .flat_map(|name| headers.get_all(*name))
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .filter_map(|method| Method::from_bytes(method.trim().to_ascii_uppercase().as_bytes()).ok())
+            .collect();
+        let max_age = headers
+            .get(MAX_AGE_HEADER)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.trim().parse::<u64>().ok())
+            .map(Duration::from_secs)
+            .unwrap_or(ttl);
+        let created = Instant::now();
+        Self {
+            allowed,
+            created,
+            expires: created + max_age.min(ttl),
+        }
+    }
+
+    pub fn is_expired(&self) -> bool {
+        Instant::now() >= self.expires
+    }
+
+    /// A preflight that didn't declare any method doesn't restrict anything.
+    pub fn allows(&self, method: &Method) -> bool {
+        self.allowed.is_empty() || self.allowed.contains(method)
+    }
+}
@@ -0,0 +1,155 @@
+use std::{
+    cmp::{Ordering, Reverse},
+    collections::BinaryHeap,
+    sync::{Arc, Mutex},
+};
+use tokio::sync::oneshot;
+
+struct Waiter {
+    priority: u8,
+    sequence: Reverse<u64>,
+    wake: oneshot::Sender<Permit>,
+}
+
+impl PartialEq for Waiter {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Waiter {}
+
+impl PartialOrd for Waiter {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Waiter {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.priority, self.sequence).cmp(&(other.priority, other.sequence))
+    }
+}
+
+struct QueueState {
+    available: usize,
+    next_sequence: u64,
+    waiters: BinaryHeap<Waiter>,
+}
+
+/// Limits the number of requests running at once. Waiting requests are started by
+/// decreasing priority, and in arrival order within the same priority.
+pub struct PriorityQueue {
+    state: Mutex<QueueState>,
+}
+
+/// Slot of a running request, released when dropped.
+pub struct Permit {
+    queue: Option<Arc<PriorityQueue>>,
+}
+
+impl PriorityQueue {
+    pub fn new(permits: usize) -> Self {
+        Self {
+            state: Mutex::new(QueueState {
+                available: permits,
+                next_sequence: 0,
+                waiters: BinaryHeap::new(),
+            }),
+        }
+    }
+
+    pub async fn acquire(self: &Arc<Self>, priority: u8) -> Permit {
+        let wait = {
+            let mut state = self.state.lock().unwrap();
+            if state.available > 0 && state.waiters.is_empty() {
+                state.available -= 1;
+                None
+            } else {
+                let (wake, wait) = oneshot::channel();
+                let sequence = Reverse(state.next_sequence);
+                state.next_sequence += 1;
+                state.waiters.push(Waiter { priority, sequence, wake });
+                Some(wait)
+            }
+        };
+        match wait {
+            // The sender is only dropped along with the queue, which we hold
+            Some(wait) => wait.await.expect("waiters are only dropped once handed a permit"),
+            None => Permit { queue: Some(self.clone()) },
+        }
+    }
+
+    /// Hands the permit over to the next waiter. A permit handed to a waiter that gives up
+    /// before receiving it is dropped along with the channel, which releases it again.
+    fn release(self: Arc<Self>) {
+        let mut permit = Permit { queue: Some(self.clone()) };
+        let mut state = self.state.lock().unwrap();
+        // Waiters that gave up (e.g. cancelled requests) are skipped
+        while let Some(waiter) = state.waiters.pop() {
+            match waiter.wake.send(permit) {
+                Ok(()) => return,
+                Err(returned) => permit = returned,
+            }
+        }
+        state.available += 1;
+        permit.queue = None;
+    }
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        if let Some(queue) = self.queue.take() {
+            queue.release();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn waiters(queue: &PriorityQueue) -> usize {
+        queue.state.lock().unwrap().waiters.len()
+    }
+
+    #[tokio::test]
+    async fn waiters_start_by_priority() {
+        let queue = Arc::new(PriorityQueue::new(1));
+        let running = queue.acquire(0).await;
+        let started = Arc::new(Mutex::new(Vec::new()));
+        let mut tasks = Vec::new();
+        for (id, priority) in [(1, 0), (2, 5), (3, 0), (4, 9), (5, 5)] {
+            let (queue, started) = (queue.clone(), started.clone());
+            tasks.push(tokio::spawn(async move {
+                let _permit = queue.acquire(priority).await;
+                started.lock().unwrap().push(id);
+            }));
+            while waiters(&queue) < id {
+                tokio::task::yield_now().await;
+            }
+        }
+
+        drop(running);
+        for task in tasks {
+            task.await.unwrap();
+        }
+        // Higher priorities first, in arrival order within the same priority
+        assert_eq!(*started.lock().unwrap(), vec![4, 2, 5, 1, 3]);
+    }
+
+    #[tokio::test]
+    async fn permit_of_a_dropped_waiter_is_released() {
+        let queue = Arc::new(PriorityQueue::new(1));
+        let running = queue.acquire(0).await;
+        let mut waiting = Box::pin(queue.acquire(0));
+        assert!(futures::poll!(waiting.as_mut()).is_pending());
+
+        // The permit is handed to the waiter, which is dropped before receiving it
+        drop(running);
+        drop(waiting);
+        assert_eq!(queue.state.lock().unwrap().available, 1);
+        tokio::time::timeout(Duration::from_secs(1), queue.acquire(0)).await.unwrap();
+    }
+}
@@ -0,0 +1,28 @@
+use crate::{error::Error, Result};
+use std::net::IpAddr;
+
+/// Resolves the host of `address` (an HTTP URI, with or without scheme) to its IP addresses,
+/// in the order returned by the resolver and without duplicates.
+pub async fn resolve(address: &str) -> Result<Vec<IpAddr>> {
+    let url = if address.contains("://") {
+        reqwest::Url::parse(address)
+    } else {
+        reqwest::Url::parse(&format!("http://{}", address))
+    };
+    let url = url
+        .map_err(|err| Error::Application(err.to_string()))?;
+    let host = url
+        .host_str()
+        .ok_or_else(|| Error::Application(format!("No host in '{}'", address)))?
+        .trim_start_matches('[')
+        .trim_end_matches(']');
+    let port = url.port_or_known_default().unwrap_or(80);
+
+    let mut addresses = Vec::new();
+    for socket_addr in tokio::net::lookup_host((host, port)).await? {
+        if !addresses.contains(&socket_addr.ip()) {
+            addresses.push(socket_addr.ip());
+        }
+    }
+    Ok(addresses)
+}
@@ -0,0 +1,24 @@
+use rand::Rng;
+use reqwest::Method;
+use std::time::Duration;
+
+/// Whether requests with `method` can be sent again without further side effects.
+pub fn is_idempotent(method: &Method) -> bool {
+    matches!(*method, Method::GET | Method::HEAD | Method::OPTIONS | Method::DELETE | Method::PUT)
+}
+
+/// Whether the outcome of an attempt is worth a retry: a connection failure, or a server error.
+pub fn is_transient(result: &reqwest::Result<reqwest::Response>) -> bool {
+    match result {
+        Ok(res) => res.status().is_server_error(),
+        Err(err) => err.is_connect() || err.is_request(),
+    }
+}
+
+/// Delay before the retry following the 1-based `attempt`: `base` doubled for each previous
+/// attempt, plus a random jitter of up to half of it.
+pub fn backoff(base: Duration, attempt: u32) -> Duration {
+    let delay = base.saturating_mul(2u32.saturating_pow(attempt.saturating_sub(1)));
+    let jitter = rand::thread_rng().gen_range(0.0..=0.5);
+    delay.saturating_add(delay.mul_f64(jitter))
+}
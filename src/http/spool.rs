@@ -0,0 +1,151 @@
+use crate::{app_state::Session, Config, Result};
+use std::{
+    path::{Path, PathBuf},
+    sync::{atomic::{AtomicU64, Ordering}, Arc},
+    time::{SystemTime, UNIX_EPOCH},
+};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio_util::io::ReaderStream;
+use tracing::{debug, warn};
+
+/// Request body spooled to a temporary file, which is deleted when dropped.
+#[derive(Debug)]
+pub struct SpoolFile {
+    path: PathBuf,
+    len: AtomicU64,
+}
+
+impl SpoolFile {
+    pub async fn create(dir: &Path, data: &[u8]) -> Result<Self> {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_nanos())
+            .unwrap_or_default();
+        let path = dir.join(format!("hps-ble-spool-{}-{}.tmp", std::process::id(), nanos));
+        tokio::fs::write(&path, data).await?;
+        debug!("Spooled {} bytes to {:?}", data.len(), &path);
+        Ok(Self { path, len: AtomicU64::new(data.len() as u64) })
+    }
+
+    pub async fn append(&self, data: &[u8]) -> Result<()> {
+        let mut file = tokio::fs::OpenOptions::new().append(true).open(&self.path).await?;
+        file.write_all(data).await?;
+        file.flush().await?;
+        self.len.fetch_add(data.len() as u64, Ordering::SeqCst);
+        Ok(())
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn size(&self) -> u64 {
+        self.len.load(Ordering::SeqCst)
+    }
+
+    /// Streams the first `len` bytes, so that later appends don't change a body already issued.
+    pub async fn body(&self, len: u64) -> Result<reqwest::Body> {
+        let file = tokio::fs::File::open(&self.path).await?;
+        Ok(reqwest::Body::wrap_stream(ReaderStream::new(file.take(len))))
+    }
+}
+
+impl Drop for SpoolFile {
+    /// The file is removed on a blocking thread when dropped within the runtime, so that the
+    /// removal doesn't stall the task dropping the last reference.
+    fn drop(&mut self) {
+        let path = std::mem::take(&mut self.path);
+        match tokio::runtime::Handle::try_current() {
+            Ok(runtime) => {
+                runtime.spawn_blocking(move || remove(&path));
+            }
+            Err(_) => remove(&path),
+        }
+    }
+}
+
+fn remove(path: &Path) {
+    match std::fs::remove_file(path) {
+        Ok(()) => debug!("Removed spool file {:?}", path),
+        Err(err) => warn!("Unable to remove spool file {:?}: {}", path, err),
+    }
+}
+
+/// Spooled body of an issued request, as long as it was when the request was issued.
+#[derive(Clone, Debug)]
+pub struct SpooledBody {
+    pub file: Arc<SpoolFile>,
+    pub len: u64,
+}
+
+impl SpooledBody {
+    pub fn new(file: &Arc<SpoolFile>) -> Self {
+        Self { file: file.clone(), len: file.size() }
+    }
+}
+
+/// Assembles the request body written to the HTTP Entity Body: the chunk at index 0 starts a new
+/// body, the later ones append to it. Once the body exceeds `--spool-threshold`, it's moved to a
+/// spool file, which receives the following chunks, so that large bodies never sit in memory.
+pub async fn write_body_chunk(session: &Session, config: &Config, chunk_index: usize, chunk: Vec<u8>) -> Result<()> {
+    let mut spool = session.request_spool.lock().await;
+    let mut value = session.request_body.write().await;
+    if chunk_index == 0 {
+        // The previous spool file is deleted, unless the last request still needs it
+        *spool = None;
+        *value = chunk;
+    } else if let Some(file) = spool.as_ref() {
+        file.append(&chunk).await?;
+    } else {
+        value.extend_from_slice(&chunk);
+    }
+
+    if spool.is_none() && config.spool_threshold.is_some_and(|threshold| value.len() > threshold) {
+        let file = SpoolFile::create(&config.spool_dir(), &value).await?;
+        *spool = Some(Arc::new(file));
+        *value = Vec::new();
+    }
+    let len = spool.as_ref().map(|file| file.size()).unwrap_or(value.len() as u64);
+    debug!("Assembled body of {} bytes{}", len, if spool.is_some() { " (spooled)" } else { "" });
+    Ok(())
+}
+
+/// Length of the body written so far, whether spooled or in memory.
+pub async fn body_len(session: &Session) -> u64 {
+    match session.request_spool.lock().await.as_ref() {
+        Some(file) => file.size(),
+        None => session.request_body.read().await.len() as u64,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn file_is_removed_in_background() {
+        let spool = SpoolFile::create(&std::env::temp_dir(), b"body").await.unwrap();
+        let path = spool.path().to_path_buf();
+        assert!(path.exists());
+
+        drop(spool);
+        let removed = async {
+            while path.exists() {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        };
+        tokio::time::timeout(Duration::from_secs(5), removed).await.unwrap();
+    }
+
+    #[test]
+    fn file_is_removed_outside_the_runtime() {
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let spool = runtime.block_on(SpoolFile::create(&std::env::temp_dir(), b"body")).unwrap();
+        let path = spool.path().to_path_buf();
+        drop(runtime);
+
+        drop(spool);
+        assert!(!path.exists());
+    }
+}
@@ -0,0 +1,220 @@
+use crate::{app_state::Session, utils, AppState, Config};
+use byteorder::{LittleEndian, WriteBytesExt};
+use std::{future::Future, sync::Arc};
+use tracing::{debug, warn};
+
+/// Sequence number of the notification marking the end of the body.
+pub const END_OF_BODY: u32 = u32::MAX;
+const SEQUENCE_SIZE: usize = 4;
+/// Body length, body chunk count and body checksum (CRC32 of no data is 0) in the HTTP Headers
+/// Body Sizes.
+const BODY_SIZE_FIELDS: [std::ops::Range<usize>; 3] = [4..8, 16..20, 24..28];
+
+fn notification(sequence: u32, data: &[u8]) -> Vec<u8> {
+    let mut value = Vec::with_capacity(SEQUENCE_SIZE + data.len());
+    value.write_u32::<LittleEndian>(sequence).unwrap();
+    value.extend_from_slice(data);
+    value
+}
+
+/// Sends the response body of `session` to `notify` as a sequence of notifications, each made of
+/// a u32 little endian sequence number followed by the chunk. Notifications are at most as large
+/// as the chunk size declared by the client, or the MTU. The last notification has `END_OF_BODY`
+/// as sequence number, followed by the number of chunks sent as u32, which is returned.
+async fn send_body<F, Fut>(session: &Session, mtu: usize, mut notify: F) -> std::io::Result<u32>
+where
+    F: FnMut(Vec<u8>) -> Fut,
+    Fut: Future<Output = std::io::Result<()>>,
+{
+    let body = session.response_body.read().await.clone();
+    let chunk_size = utils::get_chunk_size(&session.http_headers_body_chunk_idx.read().await, mtu);
+    let data_size = chunk_size.saturating_sub(SEQUENCE_SIZE).max(1);
+    let mut sent: u32 = 0;
+    for chunk in body.chunks(data_size) {
+        notify(notification(sent, chunk)).await?;
+        sent += 1;
+    }
+
+    let mut end = Vec::with_capacity(SEQUENCE_SIZE);
+    end.write_u32::<LittleEndian>(sent).unwrap();
+    notify(notification(END_OF_BODY, &end)).await?;
+    Ok(sent)
+}
+
+/// Pushes the response body to the subscribed client, see `send_body`. Each notification is sent
+/// once the previous one has been delivered. The notifier is cloned out of its slot, which is only
+/// locked to check or replace the subscription, and the notifier itself is only locked while
+/// sending each notification.
+pub async fn push_body(state: Arc<AppState>, session: Arc<Session>, mtu: usize) {
+    let Some(notifier) = session.notifiers.lock().await.body.clone() else {
+        warn!("Unable to push the body, the client isn't subscribed to the HTTP Entity Body");
+        return;
+    };
+
+    let sent = send_body(&session, mtu, |value| {
+        let notifier = notifier.clone();
+        async move { notifier.lock().await.notify(value).await }
+    })
+    .await;
+    match sent {
+        Ok(sent) => debug!("Pushed {} body chunks", sent),
+        Err(err) => {
+            warn!("Body notification error: {}", &err);
+            state.unsubscribe(&session, &notifier).await;
+        }
+    }
+}
+
+/// Starts pushing the body in background, replacing any push still running.
+pub async fn start_push_body(state: &Arc<AppState>, session: &Arc<Session>, mtu: usize) {
+    let task = tokio::spawn(push_body(state.clone(), session.clone(), mtu));
+    if let Some(previous) = session.body_stream.lock().await.replace(task.abort_handle()) {
+        previous.abort();
+    }
+}
+
+/// Starts pushing the body of the response just written when `--push-body` is set, provided that
+/// a client is subscribed to the HTTP Entity Body notifications.
+pub async fn push_after_response(state: &Arc<AppState>, session: &Arc<Session>, config: &Config, mtu: usize) {
+    if !config.push_body || session.notifiers.lock().await.body.is_none() {
+        return;
+    }
+    debug!("Pushing the body of the response through notifications");
+    start_push_body(state, session, mtu).await;
+}
+
+/// Aborts the body push, if any, and clears the body along with the chunk indexes. The body
+/// length, chunk count and checksum of the HTTP Headers Body Sizes are reset to match.
+/// Returns whether a push was running.
+pub async fn abort_push_body(session: &Session) -> bool {
+    let running = match session.body_stream.lock().await.take() {
+        Some(task) => {
+            let running = !task.is_finished();
+            task.abort();
+            running
+        }
+        None => false,
+    };
+
+    session.response_body.write().await.clear();
+    let mut sizes = session.http_headers_body_sizes.write().await;
+    for field in BODY_SIZE_FIELDS {
+        if let Some(value) = sizes.get_mut(field) {
+            value.fill(0);
+        }
+    }
+    drop(sizes);
+    let mut chunk_idxs = session.http_headers_body_chunk_idx.write().await;
+    let mut chunk_idxs_values = vec![0; 8];
+    if let Some(chunk_size) = chunk_idxs.get(8..12) {
+        chunk_idxs_values.extend_from_slice(chunk_size);
+    }
+    *chunk_idxs = chunk_idxs_values;
+    running
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DEVICE: bluer::Address = bluer::Address([0, 0, 0, 0, 0, 1]);
+
+    fn sizes(values: &[u32]) -> Vec<u8> {
+        values.iter().flat_map(|value| value.to_le_bytes()).collect()
+    }
+
+    /// Sends the body of `session`, collecting the notifications.
+    async fn notifications(session: &Session, mtu: usize) -> Vec<Vec<u8>> {
+        let mut sent = Vec::new();
+        send_body(session, mtu, |value| {
+            sent.push(value);
+            async { Ok(()) }
+        })
+        .await
+        .unwrap();
+        sent
+    }
+
+    #[tokio::test]
+    async fn notifications_are_sized_by_the_declared_chunk_size() {
+        let state = Arc::new(AppState::new());
+        let session = state.session(DEVICE).await;
+        let body: Vec<u8> = (0..30).collect();
+        *session.response_body.write().await = body.clone().into();
+        *session.http_headers_body_chunk_idx.write().await = sizes(&[0, 0, 16]);
+
+        let sent = notifications(&session, 512).await;
+        let mut expected: Vec<Vec<u8>> = body.chunks(12).enumerate().map(|(i, chunk)| notification(i as u32, chunk)).collect();
+        expected.push(notification(END_OF_BODY, &3u32.to_le_bytes()));
+        assert_eq!(sent, expected);
+    }
+
+    #[tokio::test]
+    async fn notifications_are_sized_by_the_mtu() {
+        let state = Arc::new(AppState::new());
+        let session = state.session(DEVICE).await;
+        *session.response_body.write().await = vec![7; 30].into();
+
+        let sent = notifications(&session, 20).await;
+        let lengths: Vec<usize> = sent.iter().map(Vec::len).collect();
+        assert_eq!(lengths, [20, 18, 8]);
+    }
+
+    #[tokio::test]
+    async fn pushed_chunks_after_a_response_match_the_declared_chunk_size() {
+        use crate::http::handler::{handle_http_control_point, HttpControlOption};
+        use clap::Parser;
+        use wiremock::{matchers, Mock, MockServer, ResponseTemplate};
+
+        let server = MockServer::start().await;
+        let body: Vec<u8> = (0..50).collect();
+        Mock::given(matchers::method("GET"))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(body.clone()))
+            .mount(&server)
+            .await;
+        let config = Config::parse_from(["hps-ble", "--push-body", "--allowed-ports", &server.address().port().to_string()]);
+        let state = Arc::new(AppState::new());
+        state.ready.store(true, std::sync::atomic::Ordering::SeqCst);
+        let session = state.session(DEVICE).await;
+        *session.http_uri.write().await = server.uri().into_bytes();
+        *session.http_headers_body_chunk_idx.write().await = sizes(&[0, 0, 20]);
+        handle_http_control_point(&state, vec![HttpControlOption::Get as u8], DEVICE, &config, 512).await.unwrap();
+
+        // The chunk size advertised with the response is the one the push uses
+        assert_eq!(session.http_headers_body_sizes.read().await[8..12], 20u32.to_le_bytes());
+        let sent = notifications(&session, 512).await;
+        assert!(sent.iter().all(|value| value.len() <= 20));
+        let pushed: Vec<u8> = sent[..sent.len() - 1].iter().flat_map(|value| value[SEQUENCE_SIZE..].to_vec()).collect();
+        assert_eq!(pushed, body);
+        assert_eq!(sent.last().unwrap(), &notification(END_OF_BODY, &4u32.to_le_bytes()));
+    }
+
+    #[tokio::test]
+    async fn abort_resets_the_body_sizes() {
+        let state = Arc::new(AppState::new());
+        let session = state.session(DEVICE).await;
+        *session.response_body.write().await = vec![1; 100].into();
+        *session.http_headers_body_sizes.write().await = sizes(&[20, 100, 16, 2, 7, 0xAAAA, 0xBBBB]);
+        *session.http_headers_body_chunk_idx.write().await = sizes(&[1, 3, 16]);
+
+        start_push_body(&state, &session, 16).await;
+        assert!(abort_push_body(&session).await);
+
+        assert!(session.body_stream.lock().await.is_none());
+        assert!(session.response_body.read().await.is_empty());
+        assert_eq!(*session.http_headers_body_sizes.read().await, sizes(&[20, 0, 16, 2, 0, 0xAAAA, 0]));
+        assert_eq!(*session.http_headers_body_chunk_idx.read().await, sizes(&[0, 0, 16]));
+        assert!(!abort_push_body(&session).await);
+    }
+
+    #[tokio::test]
+    async fn abort_without_checksums() {
+        let state = Arc::new(AppState::new());
+        let session = state.session(DEVICE).await;
+        *session.http_headers_body_sizes.write().await = sizes(&[20, 100, 16, 2, 7]);
+
+        assert!(!abort_push_body(&session).await);
+        assert_eq!(*session.http_headers_body_sizes.read().await, sizes(&[20, 0, 16, 2, 0]));
+        assert_eq!(*session.http_headers_body_chunk_idx.read().await, sizes(&[0, 0]));
+    }
+}
@@ -0,0 +1,123 @@
+use serde_json::Value;
+use std::collections::HashMap;
+
+/// Maximum number of values kept for the templates.
+pub const MAX_VALUES: usize = 16;
+/// Maximum size of a value kept for the templates, longer values are ignored.
+pub const MAX_VALUE_SIZE: usize = 512;
+
+enum Segment {
+    Key(String),
+    Index(usize),
+}
+
+/// Rule storing the value found at a JSONPath-lite path (`$.data.items[0].id`) of the
+/// response body under a name.
+pub struct ExtractionRule {
+    name: String,
+    path: Vec<Segment>,
+}
+
+fn parse_path(path: &str) -> Option<Vec<Segment>> {
+    let path = path.trim().strip_prefix('$')?;
+    let path = path.strip_prefix('.').unwrap_or(path);
+    let mut segments = Vec::new();
+    for part in path.split('.').filter(|part| !part.is_empty()) {
+        let (key, indexes) = part.split_once('[').unwrap_or((part, ""));
+        if !key.is_empty() {
+            segments.push(Segment::Key(key.to_string()));
+        }
+        for index in indexes.split('[').filter(|index| !index.is_empty()) {
+            segments.push(Segment::Index(index.strip_suffix(']')?.parse().ok()?));
+        }
+    }
+    Some(segments)
+}
+
+/// Parses comma separated `name=path` rules, ignoring the invalid ones.
+pub fn parse_rules(rules: &str) -> Vec<ExtractionRule> {
+    rules
+        .split(',')
+        .filter_map(|rule| {
+            let (name, path) = rule.split_once('=')?;
+            let name = name.trim();
+            if name.is_empty() {
+                return None;
+            }
+            Some(ExtractionRule { name: name.to_string(), path: parse_path(path)? })
+        })
+        .collect()
+}
+
+fn select<'a>(value: &'a Value, path: &[Segment]) -> Option<&'a Value> {
+    path.iter().try_fold(value, |value, segment| match segment {
+        Segment::Key(key) => value.get(key),
+        Segment::Index(index) => value.get(index),
+    })
+}
+
+/// Extracts the values selected by `rules` from a JSON body. Strings are taken as is, other
+/// values as JSON.
+pub fn extract(body: &[u8], rules: &[ExtractionRule]) -> Vec<(String, String)> {
+    let Ok(json) = serde_json::from_slice::<Value>(body) else {
+        return Vec::new();
+    };
+    rules
+        .iter()
+        .filter_map(|rule| {
+            let value = match select(&json, &rule.path)? {
+                Value::String(value) => value.clone(),
+                value => value.to_string(),
+            };
+            (value.len() <= MAX_VALUE_SIZE).then(|| (rule.name.clone(), value))
+        })
+        .collect()
+}
+
+/// Replaces the `{{name}}` placeholders with the stored values, unknown placeholders are
+/// left untouched.
+pub fn substitute(input: &str, values: &HashMap<String, String>) -> String {
+    let mut output = String::with_capacity(input.len());
+    let mut rest = input;
+    while let Some(start) = rest.find("{{") {
+        let Some(len) = rest[start + 2..].find("}}") else {
+            break;
+        };
+        let name = &rest[start + 2..start + 2 + len];
+        output.push_str(&rest[..start]);
+        match values.get(name.trim()) {
+            Some(value) => output.push_str(value),
+            None => output.push_str(&rest[start..start + len + 4]),
+        }
+        rest = &rest[start + len + 4..];
+    }
+    output.push_str(rest);
+    output
+}
+
+/// Replaces the placeholders of a body, which can only have them when it's UTF-8 text. Binary
+/// bodies are returned untouched.
+pub fn substitute_bytes(input: Vec<u8>, values: &HashMap<String, String>) -> Vec<u8> {
+    match String::from_utf8(input) {
+        Ok(text) => substitute(&text, values).into_bytes(),
+        Err(err) => err.into_bytes(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn binary_body_is_untouched() {
+        let values = HashMap::from([("token".to_string(), "abc".to_string())]);
+        let body = vec![0x1f, 0x8b, 0xff, b'{', b'{', b't', b'o', b'k', b'e', b'n', b'}', b'}', 0x00];
+        assert_eq!(substitute_bytes(body.clone(), &values), body);
+    }
+
+    #[test]
+    fn text_body_is_substituted() {
+        let values = HashMap::from([("token".to_string(), "abc".to_string())]);
+        assert_eq!(substitute_bytes(b"id={{token}}".to_vec(), &values), b"id=abc".to_vec());
+    }
+}
@@ -0,0 +1,77 @@
+use openssl::hash::{hash, MessageDigest};
+
+/// Handshake errors raised when the server answers the TLS handshake in plaintext, or drops it.
+const NO_TLS_ERRORS: &[&str] = &[
+    "wrong version number",
+    "packet length too long",
+    "record layer failure",
+    "http request",
+    "connection reset",
+    "unexpected eof",
+];
+
+/// Handshake errors raised when the server certificate isn't trusted.
+const UNTRUSTED_CERTIFICATE_ERRORS: &[&str] = &[
+    "certificate verify failed",
+    "self signed certificate",
+    "self-signed certificate",
+    "unable to get local issuer certificate",
+    "certificate has expired",
+    "hostname mismatch",
+    "invalid certificate",
+];
+
+/// Reason of a secure request failure due to TLS.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TlsFailure {
+    /// The server doesn't offer TLS on that port
+    Unavailable,
+    /// The server certificate isn't trusted
+    UntrustedCertificate,
+}
+
+/// Tells whether a secure request failed during the TLS handshake, and why.
+pub fn classify_failure(err: &reqwest::Error) -> Option<TlsFailure> {
+    if !err.is_connect() {
+        return None;
+    }
+    let mut source: Option<&dyn std::error::Error> = Some(err);
+    while let Some(err) = source {
+        let message = err.to_string().to_ascii_lowercase();
+        if UNTRUSTED_CERTIFICATE_ERRORS.iter().any(|pattern| message.contains(pattern)) {
+            return Some(TlsFailure::UntrustedCertificate);
+        }
+        if NO_TLS_ERRORS.iter().any(|pattern| message.contains(pattern)) {
+            return Some(TlsFailure::Unavailable);
+        }
+        source = err.source();
+    }
+    None
+}
+
+/// Details of the TLS connection a response was received on. The TLS backend of reqwest only
+/// exposes the server certificate, whose SHA-256 fingerprint is reported.
+pub struct TlsInfo {
+    pub certificate_sha256: Vec<u8>,
+}
+
+impl TlsInfo {
+    /// Info of the connection of `res`, provided the client was built with `tls_info(true)`.
+    pub fn from_response(res: &reqwest::Response) -> Option<Self> {
+        let certificate = res.extensions().get::<reqwest::tls::TlsInfo>()?.peer_certificate()?;
+        let digest = hash(MessageDigest::sha256(), certificate).ok()?;
+        Some(Self { certificate_sha256: digest.to_vec() })
+    }
+
+    /// Fingerprint as a u8 length followed by the digest.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut value = vec![self.certificate_sha256.len() as u8];
+        value.extend_from_slice(&self.certificate_sha256);
+        value
+    }
+
+    /// Fingerprint as colon separated hex bytes, the way openssl prints it.
+    pub fn fingerprint(&self) -> String {
+        self.certificate_sha256.iter().map(|byte| format!("{:02X}", byte)).collect::<Vec<_>>().join(":")
+    }
+}
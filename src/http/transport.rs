@@ -0,0 +1,95 @@
+use super::{client::RedirectDenied, handler::HttpStatusSentinel};
+use std::{error::Error as StdError, io};
+
+/// Sentinel telling why the server couldn't be reached, by looking for the cause of `err`
+/// along its chain of sources. When `proxied`, the failures to connect are told apart from
+/// the ones of the upstream proxy.
+pub fn classify_error(err: &reqwest::Error, proxied: bool) -> HttpStatusSentinel {
+    if proxied && err.is_connect() && (err.is_timeout() || proxy_unreachable(err)) {
+        return HttpStatusSentinel::ProxyUnreachable;
+    }
+    if err.is_timeout() {
+        return HttpStatusSentinel::Timeout;
+    }
+
+    let mut source: Option<&(dyn StdError + 'static)> = Some(err);
+    while let Some(current) = source {
+        if let Some(RedirectDenied(sentinel)) = current.downcast_ref::<RedirectDenied>() {
+            return *sentinel;
+        }
+        if let Some(io_err) = current.downcast_ref::<io::Error>() {
+            match io_err.kind() {
+                io::ErrorKind::ConnectionRefused => return HttpStatusSentinel::ConnectionRefused,
+                io::ErrorKind::ConnectionReset | io::ErrorKind::ConnectionAborted | io::ErrorKind::BrokenPipe => {
+                    return HttpStatusSentinel::ConnectionReset
+                }
+                io::ErrorKind::TimedOut => return HttpStatusSentinel::Timeout,
+                _ => {}
+            }
+        }
+        // Resolution failures aren't exposed as a distinct kind, only by their message
+        if current.to_string().contains("dns error") {
+            return HttpStatusSentinel::DnsFailure;
+        }
+        source = current.source();
+    }
+    HttpStatusSentinel::UpstreamError
+}
+
+/// Requests through a proxy only connect to the proxy, hence a connection or resolution
+/// failure means it's unreachable. Failures answered by the proxy itself, like a tunnel it
+/// couldn't open, concern the server instead.
+fn proxy_unreachable(err: &reqwest::Error) -> bool {
+    let mut source: Option<&(dyn StdError + 'static)> = Some(err);
+    while let Some(current) = source {
+        if let Some(io_err) = current.downcast_ref::<io::Error>() {
+            if matches!(
+                io_err.kind(),
+                io::ErrorKind::ConnectionRefused
+                    | io::ErrorKind::ConnectionReset
+                    | io::ErrorKind::ConnectionAborted
+                    | io::ErrorKind::TimedOut
+            ) {
+                return true;
+            }
+        }
+        // SOCKS failures only keep the message of their cause
+        let message = current.to_string();
+        if message.contains("dns error") || message.contains("os error") {
+            return true;
+        }
+        source = current.source();
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn connection_refused() {
+        // The listener is dropped right away, leaving a port nobody listens on
+        let port = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+        let err = reqwest::get(format!("http://127.0.0.1:{}", port)).await.unwrap_err();
+        assert_eq!(classify_error(&err, false), HttpStatusSentinel::ConnectionRefused);
+    }
+
+    #[tokio::test]
+    async fn dns_failure() {
+        // The .invalid TLD never resolves
+        let err = reqwest::get("http://hps-ble.invalid").await.unwrap_err();
+        assert_eq!(classify_error(&err, false), HttpStatusSentinel::DnsFailure);
+    }
+
+    #[tokio::test]
+    async fn unreachable_proxy() {
+        let port = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+        let client = reqwest::Client::builder()
+            .proxy(reqwest::Proxy::all(format!("http://127.0.0.1:{}", port)).unwrap())
+            .build()
+            .unwrap();
+        let err = client.get("http://example.com").send().await.unwrap_err();
+        assert_eq!(classify_error(&err, true), HttpStatusSentinel::ProxyUnreachable);
+    }
+}
@@ -2,32 +2,23 @@ pub mod app_state;
 pub mod bluetooth;
 pub mod config;
 pub mod constants;
+pub mod dry_run;
 pub mod error;
 pub mod http;
+pub mod logging;
+pub mod server;
 pub mod utils;
 
-pub use app_state::AppState;
+pub use app_state::{AppState, RunSummary};
+pub use bluetooth::application::{describe_profile, CharacteristicDescriptor};
 pub use config::Config;
 pub use error::Result;
-use tracing::info;
+pub use server::{ServerBuilder, ServerHandle};
 pub use std::sync::Arc;
 
-pub async fn run(config: Config) -> Result<()> {
-    // Initialize logger
-    tracing_subscriber::fmt::init();
-
-    info!(target: "hps_ble", "Starting HPS BLE server with config: {:?}", &config);
-
-    let state = Arc::new(AppState::new());
-    let session = bluetooth::setup_bluetooth().await?;
-    let adapter = session.default_adapter().await?;
-
-    let adv_handle = bluetooth::start_advertising(&adapter, &config).await?;
-    let app_handle = bluetooth::serve_gatt_application(&adapter, &state, &config).await?;
-
+/// Runs the server until SIGTERM or SIGINT is received.
+pub async fn run(config: Config) -> Result<RunSummary> {
+    let server = ServerBuilder::new(config).start().await?;
     utils::handle_signals().await?;
-
-    bluetooth::cleanup(adv_handle, app_handle).await;
-
-    Ok(())
-}
\ No newline at end of file
+    Ok(server.shutdown().await)
+}
@@ -0,0 +1,136 @@
+use crate::{config::{LogFormat, LogLevel}, Config};
+use clap::ValueEnum;
+use tracing::{debug, level_filters::LevelFilter};
+use tracing_subscriber::EnvFilter;
+
+/// Logged in place of the sensitive values.
+pub const REDACTED: &str = "***";
+
+impl From<LogLevel> for LevelFilter {
+    fn from(level: LogLevel) -> Self {
+        match level {
+            LogLevel::Trace => LevelFilter::TRACE,
+            LogLevel::Debug => LevelFilter::DEBUG,
+            LogLevel::Info => LevelFilter::INFO,
+            LogLevel::Warn => LevelFilter::WARN,
+            LogLevel::Error => LevelFilter::ERROR,
+        }
+    }
+}
+
+/// Installs the global subscriber writing the logs in `format`. Nothing happens when a
+/// subscriber is already installed, e.g. by the binary before parsing the arguments.
+///
+/// Events are logged from `level` up, unless `RUST_LOG` sets the level of their target
+/// (e.g. `RUST_LOG=hyper=warn`) or the default level (e.g. `RUST_LOG=trace`).
+pub fn init(format: LogFormat, level: LogLevel) {
+    let filter = EnvFilter::builder()
+        .with_default_directive(LevelFilter::from(level).into())
+        .from_env_lossy();
+    let builder = tracing_subscriber::fmt().with_env_filter(filter).with_target(true);
+    let _ = match format {
+        LogFormat::Text => builder.try_init(),
+        LogFormat::Json => builder.json().with_current_span(true).with_span_list(true).try_init(),
+    };
+}
+
+/// Value of the option `name` in the command line `args`, looked up without parsing them, so
+/// that the errors of the parsing itself are logged as requested.
+pub fn find_arg<T: ValueEnum>(args: &[String], name: &str) -> Option<T> {
+    let mut found = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let value = match arg.strip_prefix(name) {
+            Some("") => args.next().map(String::as_str),
+            Some(value) => value.strip_prefix('='),
+            None => continue,
+        };
+        if let Some(parsed) = value.and_then(|value| T::from_str(value, true).ok()) {
+            found = Some(parsed);
+        }
+    }
+    found
+}
+
+/// Value of the header `name` as it's logged: the headers listed by `--redacted-headers` are
+/// replaced by `REDACTED`, unless `--log-unredacted` is set.
+pub fn header_value<'a>(config: &Config, name: &str, value: &'a str) -> &'a str {
+    if !config.log_unredacted && config.redacted_headers.iter().any(|redacted| redacted.eq_ignore_ascii_case(name)) {
+        REDACTED
+    } else {
+        value
+    }
+}
+
+/// Logs the headers of a request or of a response (`kind`), see `header_value`.
+pub fn log_headers<'a>(config: &Config, kind: &str, headers: impl IntoIterator<Item = (&'a str, &'a str)>) {
+    for (name, value) in headers {
+        debug!("{} header '{}: {}'", kind, name, header_value(config, name, value));
+    }
+}
+
+/// Part of a body that is logged: its first `--log-body-bytes`, or all of it with
+/// `--log-unredacted`.
+pub fn body_prefix<'a>(config: &Config, body: &'a [u8]) -> &'a [u8] {
+    if config.log_unredacted {
+        body
+    } else {
+        &body[..body.len().min(config.log_body_bytes)]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+    use std::{io, sync::{Arc, Mutex}};
+
+    #[derive(Clone, Default)]
+    struct Capture(Arc<Mutex<Vec<u8>>>);
+
+    impl io::Write for Capture {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn captured_headers(config: &Config) -> String {
+        let capture = Capture::default();
+        let writer = capture.clone();
+        let subscriber = tracing_subscriber::fmt()
+            .with_max_level(LevelFilter::TRACE)
+            .with_writer(move || writer.clone())
+            .finish();
+        tracing::subscriber::with_default(subscriber, || {
+            log_headers(config, "Request", [("Accept", "*/*"), ("authorization", "Bearer s3cr3t-t0k3n")]);
+        });
+        let logs = capture.0.lock().unwrap().clone();
+        String::from_utf8(logs).unwrap()
+    }
+
+    #[test]
+    fn authorization_is_never_logged() {
+        let logs = captured_headers(&Config::parse_from(["hps-ble"]));
+        assert!(logs.contains("'Accept: */*'"));
+        assert!(logs.contains("'authorization: ***'"));
+        assert!(!logs.contains("s3cr3t-t0k3n"));
+    }
+
+    #[test]
+    fn unredacted_logs_opt_in() {
+        let logs = captured_headers(&Config::parse_from(["hps-ble", "--log-unredacted"]));
+        assert!(logs.contains("Bearer s3cr3t-t0k3n"));
+    }
+
+    #[test]
+    fn bodies_are_capped() {
+        let config = Config::parse_from(["hps-ble", "--log-body-bytes", "4"]);
+        assert_eq!(body_prefix(&config, b"password=hunter2"), b"pass");
+        assert_eq!(body_prefix(&config, b"ok"), b"ok");
+    }
+}
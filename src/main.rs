@@ -1,8 +1,25 @@
 use clap::Parser;
-use hps_ble::{run, Config, Result};
+use hps_ble::{config::{LogFormat, LogLevel}, logging, run, Config, Result};
+use tracing::error;
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    let config = Config::parse();
-    run(config).await
-}
\ No newline at end of file
+    let config = match Config::try_parse() {
+        Ok(config) => config,
+        Err(err) => {
+            // Help and version aren't errors
+            if err.use_stderr() {
+                let args: Vec<String> = std::env::args().collect();
+                logging::init(
+                    logging::find_arg(&args, "--log-format").unwrap_or(LogFormat::Text),
+                    logging::find_arg(&args, "--log-level").unwrap_or(LogLevel::Info),
+                );
+                error!(target: "hps_ble", "Invalid arguments: {}", err.to_string().lines().next().unwrap_or_default());
+            }
+            err.exit();
+        }
+    };
+    logging::init(config.log_format, config.log_level);
+    run(config).await?;
+    Ok(())
+}
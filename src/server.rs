@@ -0,0 +1,181 @@
+use crate::{bluetooth, dry_run, error::Error, http::{self, hook::ProxyHook}, logging, AppState, Config, Result, RunSummary};
+use bluer::{adv::AdvertisementHandle, gatt::local::ApplicationHandle};
+use std::{net::SocketAddr, sync::{atomic::Ordering, Arc}, time::Instant};
+use tokio::{net::TcpListener, task::JoinHandle};
+use tracing::{info, warn};
+
+/// Sets up and starts the server, for the applications embedding it.
+pub struct ServerBuilder {
+    config: Config,
+    state: Option<Arc<AppState>>,
+    hook: Option<Arc<dyn ProxyHook>>,
+    init_logging: bool,
+}
+
+impl ServerBuilder {
+    pub fn new(config: Config) -> Self {
+        Self {
+            config,
+            state: None,
+            hook: None,
+            init_logging: true,
+        }
+    }
+
+    /// Serves the requests with `state` instead of a new one, so that the caller can keep
+    /// inspecting it (e.g. its metrics).
+    pub fn state(mut self, state: Arc<AppState>) -> Self {
+        self.state = Some(state);
+        self
+    }
+
+    /// Invokes `hook` around each proxied request. It's ignored when the state given to `state`
+    /// already has a hook.
+    pub fn hook(mut self, hook: impl ProxyHook + 'static) -> Self {
+        self.hook = Some(Arc::new(hook));
+        self
+    }
+
+    /// Whether the server installs its own subscriber for the logs (the default). Applications
+    /// that already set up their logging should disable it.
+    pub fn init_logging(mut self, init_logging: bool) -> Self {
+        self.init_logging = init_logging;
+        self
+    }
+
+    /// Starts advertising and serving the GATT application (or the dry-run protocol with
+    /// `--no-bluetooth`), returning once requests are accepted.
+    pub async fn start(self) -> Result<ServerHandle> {
+        let config = self.config;
+        if self.init_logging {
+            logging::init(config.log_format, config.log_level);
+        }
+
+        info!(target: "hps_ble", "Starting HPS BLE server with config: {:?}", config.redacted());
+        #[cfg(feature = "danger-insecure-tls")]
+        if config.danger_insecure_tls {
+            warn!(target: "hps_ble", "!!! Certificate verification is DISABLED for every HTTPS request, never use --danger-insecure-tls in production !!!");
+        }
+
+        bluetooth::uuids::Uuids::new(&config.uuids)
+            .check_unique()
+            .map_err(|reason| Error::Application(format!("Invalid UUID overrides: {}", reason)))?;
+
+        let started = Instant::now();
+        let state = self.state.unwrap_or_else(|| Arc::new(AppState::new()));
+        if let Some(hook) = self.hook {
+            let _ = state.hook.set(hook);
+        }
+        let _ = state.root_certificates.set(http::client::load_root_certificates(&config.ca_cert)?);
+        if let (Some(cert), Some(key)) = (&config.client_cert, &config.client_key) {
+            let _ = state.client_identity.set(http::client::load_identity(cert, key)?);
+        }
+        if let Some(url) = &config.upstream_proxy {
+            let _ = state.upstream_proxy.set(http::client::load_upstream_proxy(url)?);
+        }
+        http::client::build_shared(&state, &config).await?;
+        if let Some(endpoint) = &config.audit_endpoint {
+            let _ = state.audit_tx.set(http::audit::spawn_audit_worker(endpoint.clone(), config.audit_queue_size, config.timeout_duration()));
+        }
+        if let Some(dir) = &config.disk_cache_dir {
+            let _ = state.disk_cache.set(http::disk_cache::DiskCache::new(dir.clone(), config.disk_cache_max_size));
+        }
+        let mut tasks = Vec::new();
+        let mut dry_run_address = None;
+        let bluetooth = if config.no_bluetooth {
+            let listener = TcpListener::bind(config.dry_run_address).await?;
+            let address = listener.local_addr()?;
+            info!(target: "hps_ble", "Bluetooth disabled, serving the dry-run protocol on {}", address);
+            state.ready.store(true, Ordering::SeqCst);
+            tasks.push(tokio::spawn(dry_run::serve(listener, state.clone(), config.clone())));
+            dry_run_address = Some(address);
+            None
+        } else {
+            let session = bluetooth::setup_bluetooth().await?;
+            let adapter = session.default_adapter().await?;
+            *state.adapter_info.write().await = bluetooth::adapter_info(&adapter).await?;
+            let _ = state.adapter.set(adapter.clone());
+
+            let uuids = bluetooth::uuids::Uuids::new(&config.uuids);
+            let adv_handle = bluetooth::start_advertising(&adapter, &config, &uuids).await?;
+            let app_handle = bluetooth::serve_gatt_application(&adapter, &state, &config, &uuids).await?;
+            state.ready.store(true, Ordering::SeqCst);
+
+            let retention = config.response_retention_duration().unwrap_or_default();
+            tasks.push(tokio::spawn(bluetooth::retention::watch_sessions(adapter.clone(), state.clone(), retention)));
+
+            let per_device_limits = config.connection_byte_budget.is_some() || config.max_requests_per_connection.is_some();
+            if per_device_limits {
+                tasks.push(tokio::spawn(bluetooth::connections::watch_connections(adapter.clone(), state.clone())));
+            }
+            Some(Bluetooth { _session: session, adv_handle, app_handle })
+        };
+
+        if let Some(interval) = config.cache_gc_interval_duration() {
+            tasks.push(tokio::spawn(http::gc::collect_garbage(state.clone(), interval, config.cache_max_age_duration())));
+        }
+
+        Ok(ServerHandle {
+            config,
+            state,
+            started,
+            bluetooth,
+            dry_run_address,
+            tasks,
+        })
+    }
+}
+
+/// Server started by `ServerBuilder::start`, running until `shutdown`.
+pub struct ServerHandle {
+    config: Config,
+    state: Arc<AppState>,
+    started: Instant,
+    /// Unset with `--no-bluetooth`.
+    bluetooth: Option<Bluetooth>,
+    dry_run_address: Option<SocketAddr>,
+    tasks: Vec<JoinHandle<()>>,
+}
+
+/// GATT service registered with BlueZ.
+struct Bluetooth {
+    /// Keeps the connection to BlueZ open.
+    _session: bluer::Session,
+    adv_handle: AdvertisementHandle,
+    app_handle: ApplicationHandle,
+}
+
+impl ServerHandle {
+    pub fn state(&self) -> &Arc<AppState> {
+        &self.state
+    }
+
+    /// Address the dry-run protocol is served on, with `--no-bluetooth` only.
+    pub fn dry_run_address(&self) -> Option<SocketAddr> {
+        self.dry_run_address
+    }
+
+    /// Rejects the new requests, lets the requests in flight end within the grace period, then
+    /// stops advertising and serving the GATT application.
+    pub async fn shutdown(self) -> RunSummary {
+        let state = self.state;
+        state.shutdown.cancel();
+        let grace = self.config.shutdown_grace_duration();
+        if state.drain(grace).await {
+            info!(target: "hps_ble", "No request in flight, shutting down");
+        } else {
+            warn!(target: "hps_ble", "Requests still in flight after {:?}, shutting down anyway", grace);
+        }
+
+        for task in self.tasks {
+            task.abort();
+        }
+        if let Some(service) = self.bluetooth {
+            bluetooth::cleanup(service.adv_handle, service.app_handle).await;
+        }
+
+        let summary = state.summary(self.started);
+        info!(target: "hps_ble", "Served {} requests ({} errors) in {:?}", summary.requests, summary.errors, summary.uptime);
+        summary
+    }
+}
@@ -1,22 +1,200 @@
 use crate::Result;
-use std::io::{Cursor, Seek};
+use bytes::Bytes;
+use std::io::Cursor;
 use byteorder::{LittleEndian, ReadBytesExt};
 use tracing::error;
 
+/// Minimum size of the chunk indexes, the headers and the body index.
+pub const CHUNK_INDEXES_SIZE: usize = 8;
+
 pub fn get_chunk_index(chunk_idx_buffer: &[u8], is_headers: bool) -> Result<usize> {
     let mut cursor = Cursor::new(chunk_idx_buffer);
-    let index = if is_headers {
-        cursor.read_u32::<LittleEndian>()
-    } else {
-        cursor.seek(std::io::SeekFrom::Start(4))?;
-        cursor.read_u32::<LittleEndian>()
-    };
-
-    match index {
+    cursor.set_position(if is_headers { 0 } else { 4 });
+    match cursor.read_u32::<LittleEndian>() {
         Ok(idx) => Ok(idx as usize),
         Err(e) => {
             error!(target: "bluetooth", "Failed to read chunk index: {}", e);
-            Ok(0) // Default to 0 if we can't read the index
+            Err(e.into())
+        }
+    }
+}
+
+/// Whether a write of the chunk indexes carries both the headers and the body index, the chunk
+/// size being optional.
+pub fn is_valid_chunk_indexes(value: &[u8]) -> bool {
+    value.len() >= CHUNK_INDEXES_SIZE
+}
+
+pub fn get_chunk_size(chunk_idx_buffer: &[u8], effective_mtu: usize) -> usize {
+    let mut cursor = Cursor::new(chunk_idx_buffer);
+    cursor.set_position(8);
+    match cursor.read_u32::<LittleEndian>() {
+        Ok(size) if size > 0 => (size as usize).min(effective_mtu),
+        _ => effective_mtu,
+    }
+}
+
+/// Returns the chunk at `chunk_index` of `data` split in chunks of `chunk_size` bytes, the last
+/// one possibly shorter. Chunks past the end of the data are empty.
+pub fn get_chunk(data: &[u8], chunk_index: usize, chunk_size: usize) -> &[u8] {
+    let start = chunk_index.saturating_mul(chunk_size);
+    if start >= data.len() {
+        return &[];
+    }
+    let end = start.saturating_add(chunk_size).min(data.len());
+    &data[start..end]
+}
+
+/// Like `get_chunk`, the chunk sharing the memory of `data` instead of borrowing it.
+pub fn get_chunk_bytes(data: &Bytes, chunk_index: usize, chunk_size: usize) -> Bytes {
+    let start = chunk_index.saturating_mul(chunk_size);
+    if start >= data.len() {
+        return Bytes::new();
+    }
+    let end = start.saturating_add(chunk_size).min(data.len());
+    data.slice(start..end)
+}
+
+/// Number of chunks of `chunk_size` bytes needed to read `len` bytes.
+pub fn get_chunk_count(len: usize, chunk_size: usize) -> u32 {
+    len.div_ceil(chunk_size.max(1)).min(u32::MAX as usize) as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use byteorder::WriteBytesExt;
+    use proptest::prelude::*;
+
+    /// Reads every chunk of `data` as clients do, through the body index of the chunk indexes,
+    /// plus the chunk past the last one.
+    fn read_chunks(data: &[u8], chunk_size: usize) -> Vec<Vec<u8>> {
+        let count = get_chunk_count(data.len(), chunk_size) as usize;
+        (0..=count)
+            .map(|index| {
+                let mut indexes = vec![0; 4];
+                indexes.write_u32::<LittleEndian>(index as u32).unwrap();
+                let index = get_chunk_index(&indexes, false).unwrap();
+                let chunk = get_chunk(data, index, chunk_size);
+                assert_eq!(get_chunk_bytes(&Bytes::copy_from_slice(data), index, chunk_size), chunk);
+                chunk.to_vec()
+            })
+            .collect()
+    }
+
+    fn assert_reconstructed(data: &[u8], chunk_size: usize) {
+        let mut chunks = read_chunks(data, chunk_size);
+        // Only the chunk past the last one is empty
+        assert!(chunks.pop().unwrap().is_empty());
+        assert!(chunks.iter().all(|chunk| !chunk.is_empty() && chunk.len() <= chunk_size));
+        if let Some((last, full)) = chunks.split_last() {
+            assert!(full.iter().all(|chunk| chunk.len() == chunk_size));
+            let remainder = data.len() % chunk_size;
+            assert_eq!(last.len(), if remainder == 0 { chunk_size } else { remainder });
+        }
+        assert_eq!(chunks.concat(), data);
+    }
+
+    proptest! {
+        #[test]
+        fn chunks_reconstruct_the_data(data in prop::collection::vec(any::<u8>(), 0..4096), chunk_size in 1usize..600) {
+            assert_reconstructed(&data, chunk_size);
+        }
+
+        #[test]
+        fn data_of_a_multiple_of_the_chunk_size(chunks in 0usize..16, chunk_size in 1usize..600) {
+            let data: Vec<u8> = (0..chunks * chunk_size).map(|i| i as u8).collect();
+            prop_assert_eq!(get_chunk_count(data.len(), chunk_size) as usize, chunks);
+            assert_reconstructed(&data, chunk_size);
+        }
+
+        #[test]
+        fn data_shorter_than_a_chunk(chunk_size in 2usize..600, len in 1usize..600) {
+            let data: Vec<u8> = (0..len % chunk_size).map(|i| i as u8).collect();
+            prop_assume!(!data.is_empty());
+            prop_assert_eq!(get_chunk_count(data.len(), chunk_size), 1);
+            assert_reconstructed(&data, chunk_size);
+        }
+    }
+
+    #[test]
+    fn short_chunk_indexes_are_rejected() {
+        assert!(get_chunk_index(&[], true).is_err());
+        assert!(get_chunk_index(&[1, 0, 0, 0, 2, 0], false).is_err());
+        assert_eq!(get_chunk_index(&[1, 0, 0, 0, 2, 0], true).unwrap(), 1);
+        assert_eq!(get_chunk_index(&[1, 0, 0, 0, 2, 0, 0, 0], false).unwrap(), 2);
+    }
+
+    #[test]
+    fn short_chunk_index_writes_are_rejected() {
+        assert!(!is_valid_chunk_indexes(&[]));
+        assert!(!is_valid_chunk_indexes(&[1, 0, 0, 0, 2, 0, 0]));
+        assert!(is_valid_chunk_indexes(&[1, 0, 0, 0, 2, 0, 0, 0]));
+        assert!(is_valid_chunk_indexes(&[1, 0, 0, 0, 2, 0, 0, 0, 16, 0, 0, 0]));
+    }
+
+    #[test]
+    fn declared_chunk_size() {
+        let indexes = [0, 0, 0, 0, 0, 0, 0, 0, 16, 0, 0, 0];
+        assert_eq!(get_chunk_size(&indexes, 512), 16);
+        // Capped to the MTU, and ignored when missing, truncated or zero
+        assert_eq!(get_chunk_size(&indexes, 10), 10);
+        assert_eq!(get_chunk_size(&indexes[..8], 512), 512);
+        assert_eq!(get_chunk_size(&indexes[..10], 512), 512);
+        assert_eq!(get_chunk_size(&[0; 12], 512), 512);
+
+        let data: Vec<u8> = (0..40).collect();
+        let chunk_size = get_chunk_size(&indexes, 512);
+        assert_eq!(get_chunk_count(data.len(), chunk_size), 3);
+        assert_eq!(get_chunk(&data, 2, chunk_size), &data[32..]);
+    }
+
+    #[test]
+    fn index_far_past_the_end_reads_an_empty_chunk() {
+        let data: Vec<u8> = (0..10).collect();
+        let index = get_chunk_index(&[0, 0, 0, 0, 0xff, 0xff, 0xff, 0xff], false).unwrap();
+        assert!(get_chunk(&data, index, 4).is_empty());
+        assert!(index as u32 > get_chunk_count(data.len(), 4));
+    }
+
+    #[test]
+    fn chunk_count_at_boundaries() {
+        assert_eq!(get_chunk_count(0, 20), 0);
+        assert_eq!(get_chunk_count(1, 20), 1);
+        assert_eq!(get_chunk_count(19, 20), 1);
+        assert_eq!(get_chunk_count(20, 20), 1);
+        assert_eq!(get_chunk_count(21, 20), 2);
+        assert_eq!(get_chunk_count(40, 20), 2);
+        assert_eq!(get_chunk_count(41, 20), 3);
+    }
+
+    #[test]
+    fn last_chunk_is_shorter() {
+        let data: Vec<u8> = (0..10).collect();
+        assert_eq!(get_chunk(&data, 0, 4), &[0, 1, 2, 3]);
+        assert_eq!(get_chunk(&data, 2, 4), &[8, 9]);
+    }
+
+    #[test]
+    fn chunks_past_the_end_are_empty() {
+        let data: Vec<u8> = (0..10).collect();
+        assert!(get_chunk(&data, 3, 4).is_empty());
+        assert!(get_chunk(&data, usize::MAX, 4).is_empty());
+        assert!(get_chunk(&[], 0, 4).is_empty());
+    }
+
+    #[test]
+    fn data_of_exact_multiple_of_the_chunk_size() {
+        let data: Vec<u8> = (0..8).collect();
+        assert_eq!(get_chunk(&data, 1, 4), &[4, 5, 6, 7]);
+        assert!(get_chunk(&data, 2, 4).is_empty());
+    }
+
+    #[test]
+    fn byte_chunks_match_the_borrowed_ones() {
+        let data = Bytes::from((0..10).collect::<Vec<u8>>());
+        for index in [0, 1, 2, 3, usize::MAX] {
+            assert_eq!(get_chunk_bytes(&data, index, 4), get_chunk(&data, index, 4));
         }
     }
-}
\ No newline at end of file
+}
@@ -1,5 +1,5 @@
 mod signals;
 mod bluetooth;
 
-pub use bluetooth::get_chunk_index;
+pub use bluetooth::{get_chunk, get_chunk_bytes, get_chunk_count, get_chunk_index, get_chunk_size, is_valid_chunk_indexes, CHUNK_INDEXES_SIZE};
 pub use signals::handle_signals;
\ No newline at end of file